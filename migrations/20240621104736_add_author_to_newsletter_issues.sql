@@ -0,0 +1,3 @@
+ALTER TABLE newsletter_issues
+ADD COLUMN published_by uuid NULL
+    REFERENCES users (user_id);
@@ -0,0 +1,12 @@
+BEGIN;
+    ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+
+    -- Hex digits are alphanumeric, so backfilled tokens follow the same format as new ones
+    UPDATE subscriptions
+        SET unsubscribe_token = substr(md5(random()::text || id::text), 1, 25)
+        WHERE unsubscribe_token IS NULL;
+
+    ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+    ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key
+        UNIQUE (unsubscribe_token);
+COMMIT;
@@ -0,0 +1,10 @@
+BEGIN;
+    -- Tokens issued before this migration get a fresh validity window
+    ALTER TABLE subscription_tokens
+        ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+    ALTER TABLE subscription_tokens
+        ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours';
+
+    ALTER TABLE subscription_tokens ALTER COLUMN created_at DROP DEFAULT;
+    ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+COMMIT;
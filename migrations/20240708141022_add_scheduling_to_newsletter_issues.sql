@@ -0,0 +1,8 @@
+BEGIN;
+    -- Issues are either 'scheduled', 'published' or 'cancelled'
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+    ALTER TABLE newsletter_issues ALTER COLUMN status DROP DEFAULT;
+    ALTER TABLE newsletter_issues ADD COLUMN send_at timestamptz NULL;
+    -- Scheduled issues get their publication date once they actually go out
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+COMMIT;
@@ -0,0 +1,37 @@
+BEGIN;
+    CREATE TABLE lists(
+        list_id uuid NOT NULL,
+        slug TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        created_at timestamptz NOT NULL,
+        PRIMARY KEY (list_id)
+    );
+    -- The single implicit list everybody subscribed to so far
+    INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES (gen_random_uuid(), 'default', 'Newsletter', now());
+
+    -- Confirmation status is tracked per list from now on
+    CREATE TABLE list_memberships(
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id) ON UPDATE CASCADE,
+        list_id uuid NOT NULL
+            REFERENCES lists (list_id),
+        status TEXT NOT NULL,
+        subscribed_at timestamptz NOT NULL,
+        PRIMARY KEY (subscriber_id, list_id)
+    );
+    INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+        SELECT s.id, l.list_id, s.status, s.subscribed_at
+        FROM subscriptions s, lists l
+        WHERE l.slug = 'default';
+    ALTER TABLE subscriptions DROP COLUMN status;
+
+    -- Confirmation links confirm the membership they were sent for
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE subscription_tokens SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE newsletter_issues SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN;
+    CREATE TABLE subscriber_tags(
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id) ON UPDATE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (subscriber_id, tag)
+    );
+
+    -- Segment expression restricting the recipients of an issue, NULL targets the whole list
+    ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
+COMMIT;
@@ -0,0 +1,30 @@
+BEGIN;
+    -- Links found in the html content of an issue, those are the ones whose clicks are tracked
+    CREATE TABLE issue_links(
+        link_id uuid NOT NULL,
+        newsletter_issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (newsletter_issue_id),
+        url TEXT NOT NULL,
+        PRIMARY KEY (link_id),
+        UNIQUE (newsletter_issue_id, url)
+    );
+
+    -- Handed out per delivered email, a token without a link identifies the open pixel
+    CREATE TABLE tracking_tokens(
+        tracking_token TEXT NOT NULL,
+        newsletter_issue_id uuid NOT NULL
+            REFERENCES newsletter_issues (newsletter_issue_id),
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id) ON UPDATE CASCADE,
+        link_id uuid NULL
+            REFERENCES issue_links (link_id),
+        PRIMARY KEY (tracking_token)
+    );
+
+    CREATE TABLE tracking_events(
+        tracking_token TEXT NOT NULL
+            REFERENCES tracking_tokens (tracking_token),
+        occurred_at timestamptz NOT NULL
+    );
+    CREATE INDEX tracking_events_tracking_token_idx ON tracking_events (tracking_token);
+COMMIT;
@@ -0,0 +1,2 @@
+-- Where test sends of newsletter issues go, publishers created so far have none
+ALTER TABLE users ADD COLUMN email TEXT NULL;
@@ -0,0 +1,23 @@
+BEGIN;
+    CREATE TABLE drafts(
+        draft_id uuid NOT NULL,
+        created_by uuid NOT NULL
+            REFERENCES users (user_id),
+        created_at timestamptz NOT NULL,
+        -- Set once the draft went out, published drafts cannot be edited anymore
+        published_at timestamptz NULL,
+        PRIMARY KEY (draft_id)
+    );
+
+    -- Every save is kept, the latest revision holds the current content of the draft
+    CREATE TABLE draft_revisions(
+        draft_id uuid NOT NULL
+            REFERENCES drafts (draft_id) ON DELETE CASCADE,
+        revision INT NOT NULL,
+        content JSONB NOT NULL,
+        saved_by uuid NOT NULL
+            REFERENCES users (user_id),
+        saved_at timestamptz NOT NULL,
+        PRIMARY KEY (draft_id, revision)
+    );
+COMMIT;
@@ -0,0 +1,35 @@
+BEGIN;
+    -- Proof of how and when subscribers gave or withdrew their consent, rows are never changed
+    CREATE TABLE consent_events(
+        consent_event_id uuid NOT NULL,
+        subscriber_id uuid NOT NULL
+            REFERENCES subscriptions (id) ON DELETE CASCADE,
+        list_id uuid NOT NULL
+            REFERENCES lists (list_id),
+        event_type TEXT NOT NULL
+            CHECK (event_type IN ('subscribe', 'confirm', 'unsubscribe')),
+        occurred_at timestamptz NOT NULL,
+        ip_address TEXT NULL,
+        user_agent TEXT NULL,
+        token TEXT NULL,
+        source TEXT NOT NULL,
+        PRIMARY KEY (consent_event_id)
+    );
+    CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id);
+
+    -- Events only go away together with the subscriber they belong to
+    CREATE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+    BEGIN
+        IF TG_OP = 'DELETE' AND NOT EXISTS (
+            SELECT 1 FROM subscriptions WHERE id = OLD.subscriber_id
+        ) THEN
+            RETURN OLD;
+        END IF;
+        RAISE EXCEPTION 'consent_events is append-only';
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER consent_events_append_only
+        BEFORE UPDATE OR DELETE ON consent_events
+        FOR EACH ROW EXECUTE FUNCTION reject_consent_event_changes();
+COMMIT;
@@ -0,0 +1,6 @@
+-- Keyed hashes of erased email addresses, proof of erasure that cannot be turned back into an address
+CREATE TABLE erased_subscribers(
+    tombstone_hash TEXT NOT NULL,
+    erased_at timestamptz NOT NULL,
+    PRIMARY KEY (tombstone_hash)
+);
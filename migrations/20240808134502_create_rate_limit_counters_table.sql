@@ -0,0 +1,7 @@
+-- Fixed window request counters, shared by every instance of the application
+CREATE TABLE rate_limit_counters(
+    key TEXT NOT NULL,
+    hits INT NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (key)
+);
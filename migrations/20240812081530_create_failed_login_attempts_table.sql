@@ -0,0 +1,9 @@
+-- Consecutive failed logins per username and per client ip, used to lock out password guessing
+CREATE TABLE failed_login_attempts(
+    scope TEXT NOT NULL CHECK (scope IN ('username', 'ip')),
+    subject TEXT NOT NULL,
+    failures INT NOT NULL,
+    last_failure_at timestamptz NOT NULL,
+    locked_until timestamptz NULL,
+    PRIMARY KEY (scope, subject)
+);
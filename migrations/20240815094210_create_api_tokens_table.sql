@@ -0,0 +1,14 @@
+-- Tokens for programmatic publishing, only their SHA-256 hash is kept
+CREATE TABLE api_tokens(
+    api_token_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL,
+    PRIMARY KEY (api_token_id)
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,4 @@
+-- Existing users keep every right they had, new users must be given a role explicitly
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'admin'
+    CHECK (role IN ('admin', 'publisher', 'editor'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
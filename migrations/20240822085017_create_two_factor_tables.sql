@@ -0,0 +1,19 @@
+-- TOTP enrolments, the secret must stay readable to verify codes. An enrolment only protects the
+-- account once a first code has been confirmed
+CREATE TABLE two_factor_enrolments(
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    secret TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    -- Codes of this time step or earlier are spent, so that a code cannot be replayed
+    last_used_step BIGINT NULL,
+    PRIMARY KEY (user_id)
+);
+
+-- Single use codes for a lost authenticator, only their SHA-256 hash is kept
+CREATE TABLE two_factor_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,6 @@
+-- Bounces and complaints are about the address, they carry over to every list it joins later
+ALTER TABLE subscriptions ADD COLUMN suppression TEXT NULL
+    CHECK (suppression IN ('bounced', 'complained'));
+UPDATE subscriptions s SET suppression = m.status
+    FROM list_memberships m
+    WHERE m.subscriber_id = s.id AND m.status IN ('bounced', 'complained');
@@ -0,0 +1,125 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Makes leaked tokens easy to spot, e.g. by secret scanners
+const TOKEN_PREFIX: &str = "nlt_";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiTokenScope {
+    // Publish, schedule and test-send issues
+    Publish,
+    // Preview issues, list scheduled issues and read their stats
+    Read,
+}
+
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::Publish => "publish",
+            ApiTokenScope::Read => "read",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "publish" => Ok(ApiTokenScope::Publish),
+            "read" => Ok(ApiTokenScope::Read),
+            other => Err(format!(
+                "{} is not a valid api token scope, use publish or read.",
+                other
+            )),
+        }
+    }
+}
+
+// The owner of a valid token, and what the token allows
+#[derive(Debug)]
+pub struct ApiTokenIdentity {
+    pub api_token_id: Uuid,
+    pub user_id: Uuid,
+    pub scopes: Vec<String>,
+}
+
+impl ApiTokenIdentity {
+    pub fn allows(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+}
+
+pub fn generate_api_token() -> SecretString {
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{}{}", TOKEN_PREFIX, token))
+}
+
+// Tokens are long and random, a fast hash is enough to keep them safe at rest
+pub fn hash_api_token(token: &SecretString) -> String {
+    hex::encode(Sha256::digest(token.expose_secret().as_bytes()))
+}
+
+// Looks up a token that is neither revoked nor expired, nor owned by a disabled user, and marks
+// it as used
+#[tracing::instrument(name = "Authenticate api token", skip(db_pool, token))]
+pub async fn authenticate_api_token(
+    db_pool: &PgPool,
+    token: &SecretString,
+) -> Result<Option<ApiTokenIdentity>, anyhow::Error> {
+    let identity = sqlx::query_as!(
+        ApiTokenIdentity,
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE t.token_hash = $1
+            AND t.revoked_at IS NULL
+            AND (t.expires_at IS NULL OR t.expires_at > now())
+            AND u.user_id = t.user_id
+            AND NOT u.disabled
+        RETURNING t.api_token_id, t.user_id, t.scopes
+        "#,
+        hash_api_token(token)
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up the api token.")?;
+
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_tokens_are_prefixed_and_unique() {
+        let a = generate_api_token();
+        let b = generate_api_token();
+
+        assert!(a.expose_secret().starts_with(TOKEN_PREFIX));
+        assert_eq!(a.expose_secret().len(), TOKEN_PREFIX.len() + 40);
+        assert_ne!(a.expose_secret(), b.expose_secret());
+    }
+
+    #[test]
+    fn test_hashes_are_stable_and_do_not_contain_the_token() {
+        let token = generate_api_token();
+
+        let hash = hash_api_token(&token);
+
+        assert_eq!(hash, hash_api_token(&token));
+        assert!(!hash.contains(&token.expose_secret()[TOKEN_PREFIX.len()..]));
+    }
+
+    #[test]
+    fn test_unknown_scopes_are_rejected() {
+        assert_eq!(ApiTokenScope::parse("read"), Ok(ApiTokenScope::Read));
+        assert!(ApiTokenScope::parse("admin").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret, SecretString};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    login_attempts::{begin_attempt, LockoutCheck, LoginAttempt},
+    routes::error_chain_fmt,
+    telemetry::spawn_blocking_thread_with_tracing,
+    two_factor::{verify_second_factor, TwoFactorError},
+    types::NewPassword,
+};
+
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+// What the caller knows about the second factor of users enrolled in two-factor authentication
+pub enum SecondFactor {
+    // The user proved it already, e.g. when they logged in
+    AlreadyVerified,
+    // The code sent along with the password, if any
+    Code(Option<SecretString>),
+}
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error("Too many failed attempts, please try again later.")]
+    LockedOut { retry_after: Duration },
+    #[error("A two-factor code is required.")]
+    SecondFactorRequired,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+// Locked out usernames and ips are turned away before the costly password verification. A wrong
+// two-factor code counts as a failed attempt, like a wrong password
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, second_factor, db_pool)
+)]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    second_factor: SecondFactor,
+    client_ip: Option<&str>,
+    db_pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let username = credentials.username.clone();
+    let attempt = LoginAttempt {
+        username: &username,
+        client_ip,
+    };
+    let pending_attempt = match begin_attempt(db_pool, &attempt).await? {
+        LockoutCheck::Allowed(pending_attempt) => pending_attempt,
+        LockoutCheck::LockedOut(retry_after) => {
+            tracing::warn!(
+                security.event = "login_rejected",
+                security.username = %username,
+                security.client_ip = ?client_ip,
+                security.retry_after_seconds = retry_after.as_secs(),
+                "Rejected a login attempt while locked out"
+            );
+            return Err(AuthError::LockedOut { retry_after });
+        }
+    };
+
+    match verify_credentials(credentials, db_pool).await {
+        Ok(user_id) => {
+            if let SecondFactor::Code(code) = second_factor {
+                match verify_second_factor(db_pool, user_id, code.as_ref(), Utc::now()).await {
+                    Ok(()) => {}
+                    Err(TwoFactorError::Required) => {
+                        pending_attempt.cancel(db_pool).await?;
+                        return Err(AuthError::SecondFactorRequired);
+                    }
+                    Err(TwoFactorError::InvalidCode) => {
+                        tracing::warn!(
+                            security.event = "two_factor_failed",
+                            security.username = %username,
+                            security.client_ip = ?client_ip,
+                            "Failed two-factor verification"
+                        );
+                        pending_attempt.fail();
+                        return Err(AuthError::InvalidCredentials(anyhow::anyhow!(
+                            "Invalid two-factor code"
+                        )));
+                    }
+                    Err(TwoFactorError::UnexpectedError(e)) => return Err(e.into()),
+                }
+            }
+            pending_attempt.succeed(db_pool).await?;
+            Ok(user_id)
+        }
+        Err(AuthError::InvalidCredentials(e)) => {
+            tracing::warn!(
+                security.event = "login_failed",
+                security.username = %username,
+                security.client_ip = ?client_ip,
+                "Failed login attempt"
+            );
+            pending_attempt.fail();
+            Err(AuthError::InvalidCredentials(e))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn verify_credentials(credentials: Credentials, db_pool: &PgPool) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    // Verify against a fallback hash for unknown users, so the response time does not leak
+    // which usernames exist
+    let mut expected_password_hash = Secret::new(
+        "$argon2id$v=19$m=15000,t=2,p=1$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
+            .to_string(),
+    );
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(db_pool, &credentials.username).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_thread_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task")??;
+
+    user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username"))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+fn verify_password_hash(
+    expected_password_hash: SecretString,
+    password_candidate: SecretString,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse PHC string")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(db_pool, username))]
+async fn get_stored_credentials(
+    db_pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, SecretString)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash
+        FROM users
+        WHERE username = $1 AND NOT disabled
+        "#,
+        username
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform query to retrieve stored credentials")?
+    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Get username", skip(db_pool))]
+pub async fn get_username(db_pool: &PgPool, user_id: Uuid) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to perform query to retrieve a username")?;
+
+    Ok(row.username)
+}
+
+#[tracing::instrument(name = "Change password", skip(password, db_pool))]
+pub async fn change_password(
+    user_id: Uuid,
+    password: NewPassword,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let password_hash = spawn_blocking_thread_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task")??;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to change the user's password in the database")?;
+
+    Ok(())
+}
+
+pub fn compute_password_hash(password: NewPassword) -> Result<SecretString, anyhow::Error> {
+    let password: SecretString = password.into();
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)?
+    .to_string();
+
+    Ok(Secret::new(password_hash))
+}
@@ -0,0 +1,103 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_tokens::ApiTokenScope;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    // Everything, including managing users
+    Admin,
+    // Sends and schedules issues
+    Publisher,
+    // Writes drafts, somebody else sends them
+    Editor,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Publisher => "publisher",
+            Role::Editor => "editor",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "publisher" => Ok(Role::Publisher),
+            "editor" => Ok(Role::Editor),
+            other => Err(format!(
+                "{} is not a valid role, use admin, publisher or editor.",
+                other
+            )),
+        }
+    }
+}
+
+// An action on newsletter issues, along with the roles and api token scope it requires
+pub trait Permission {
+    const NAME: &'static str;
+    const SCOPE: ApiTokenScope;
+
+    fn is_granted_to(role: Role) -> bool;
+}
+
+pub struct SendNewsletters;
+
+impl Permission for SendNewsletters {
+    const NAME: &'static str = "send newsletters";
+    const SCOPE: ApiTokenScope = ApiTokenScope::Publish;
+
+    fn is_granted_to(role: Role) -> bool {
+        matches!(role, Role::Admin | Role::Publisher)
+    }
+}
+
+pub struct ReadNewsletters;
+
+impl Permission for ReadNewsletters {
+    const NAME: &'static str = "read newsletters";
+    const SCOPE: ApiTokenScope = ApiTokenScope::Read;
+
+    fn is_granted_to(_role: Role) -> bool {
+        true
+    }
+}
+
+// None for unknown and disabled users
+#[tracing::instrument(name = "Get user role", skip(db_pool))]
+pub async fn get_role(db_pool: &PgPool, user_id: Uuid) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT role FROM users WHERE user_id = $1 AND NOT disabled",
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up the user's role.")?;
+
+    row.map(|row| Role::parse(&row.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_round_trip() {
+        for role in [Role::Admin, Role::Publisher, Role::Editor] {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert!(Role::parse("owner").is_err());
+    }
+
+    #[test]
+    fn test_editors_cannot_send_newsletters() {
+        assert!(SendNewsletters::is_granted_to(Role::Admin));
+        assert!(SendNewsletters::is_granted_to(Role::Publisher));
+        assert!(!SendNewsletters::is_granted_to(Role::Editor));
+        assert!(ReadNewsletters::is_granted_to(Role::Editor));
+    }
+}
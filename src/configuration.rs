@@ -5,13 +5,17 @@ use sqlx::{
     ConnectOptions,
 };
 
-use crate::types::SubscriberEmail;
+use crate::{
+    email_client::{EmailClient, FileSinkTransport, PostmarkTransport, SmtpTransport},
+    types::SubscriberEmail,
+};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub rate_limit: RateLimitSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -31,14 +35,87 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub address: String,
     pub base_url: String,
+    pub hmac_secret: SecretString,
+    // Keys the erasure tombstones, kept apart from the session key so either can be rotated alone
+    pub tombstone_secret: SecretString,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub backend: EmailBackend,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub http_client_timeout_ms: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file_sink_directory: Option<String>,
+    pub webhook: WebhookSettings,
+    // Addresses, besides the publisher's own, that test sends of an issue may go to
+    #[serde(default)]
+    pub test_recipients: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailBackend {
+    #[default]
+    Postmark,
+    Smtp,
+    FileSink,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: String,
+    pub password: SecretString,
+}
+
+// Limits on requests that make us send confirmation emails, every limit is counted separately
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    #[serde(default)]
+    pub store: RateLimitStoreKind,
+    // Only behind a reverse proxy can the client address be taken from X-Forwarded-For, anyone
+    // could set it otherwise. The proxy must append the address it received the request from
+    #[serde(default)]
+    pub behind_proxy: bool,
+    pub per_ip: RateLimit,
+    pub per_email: RateLimit,
+    pub per_domain: RateLimit,
+}
+
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitStoreKind {
+    // Counters are lost on restart and not shared between instances
+    #[default]
+    InMemory,
+    Postgres,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct RateLimit {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+}
+
+impl RateLimit {
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+}
+
+// Credentials the email provider presents, as basic auth, when reporting delivery events
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: SecretString,
 }
 
 impl EmailClientSettings {
@@ -49,6 +126,39 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.http_client_timeout_ms)
     }
+
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        match self.backend {
+            EmailBackend::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+            ),
+            EmailBackend::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The smtp settings are required by the smtp email backend.");
+                let transport = SmtpTransport::new(
+                    &smtp.host,
+                    smtp.port,
+                    smtp.username,
+                    smtp.password,
+                    timeout,
+                )
+                .expect("Failed to build the smtp email transport.");
+                EmailClient::new(sender_email, transport)
+            }
+            EmailBackend::FileSink => {
+                let directory = self.file_sink_directory.expect(
+                    "The file_sink_directory setting is required by the file_sink email backend.",
+                );
+                let transport = FileSinkTransport::new(directory)
+                    .expect("Failed to build the file sink email transport.");
+                EmailClient::new(sender_email, transport)
+            }
+        }
+    }
 }
 
 pub enum Environment {
@@ -93,16 +203,53 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let env_configuration_file = configuration_path.join(format!("{}.yaml", environment.as_str()));
 
     let settings = config::Config::builder()
-        .add_source(config::File::from(base_configuration_file))
+        .add_source(config::File::from(base_configuration_file.clone()))
         .add_source(config::File::from(env_configuration_file))
         .add_source(
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
                 .separator("__"),
         )
-        .build()?;
+        .build()?
+        .try_deserialize::<Settings>()?;
+
+    if let Environment::Production = environment {
+        let base_settings = config::Config::builder()
+            .add_source(config::File::from(base_configuration_file))
+            .build()?;
+        reject_development_secrets(&settings, &base_settings)?;
+    }
+
+    Ok(settings)
+}
+
+// The secrets in base.yaml are public, a production deployment that forgot to provide one of
+// them through the environment must not start
+fn reject_development_secrets(
+    settings: &Settings,
+    base_settings: &config::Config,
+) -> Result<(), config::ConfigError> {
+    let secrets = [
+        ("application.hmac_secret", &settings.application.hmac_secret),
+        (
+            "application.tombstone_secret",
+            &settings.application.tombstone_secret,
+        ),
+        (
+            "email_client.webhook.password",
+            &settings.email_client.webhook.password,
+        ),
+    ];
+    for (key, secret) in secrets {
+        if *secret.expose_secret() == base_settings.get_string(key)? {
+            return Err(config::ConfigError::Message(format!(
+                "{} still has its development value from base.yaml, it must be set through the environment",
+                key
+            )));
+        }
+    }
 
-    settings.try_deserialize::<Settings>()
+    Ok(())
 }
 
 impl DatabaseSettings {
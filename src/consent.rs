@@ -0,0 +1,127 @@
+use actix_web::{http::header::USER_AGENT, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::rate_limiting::ClientIp;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ConsentEventType {
+    Subscribe,
+    Confirm,
+    Unsubscribe,
+}
+
+impl ConsentEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventType::Subscribe => "subscribe",
+            ConsentEventType::Confirm => "confirm",
+            ConsentEventType::Unsubscribe => "unsubscribe",
+        }
+    }
+}
+
+// Where a consent event came from, as seen by the server
+#[derive(Debug, Default)]
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ConsentContext {
+    // The address follows the same trusted proxy rule as the rate limiter, so that clients
+    // cannot put somebody else's address on record
+    pub fn from_request(request: &HttpRequest) -> Self {
+        ConsentContext {
+            ip_address: ClientIp::for_request(request).0,
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+pub struct ConsentEvent<'a> {
+    pub subscriber_id: Uuid,
+    pub list_id: Uuid,
+    pub event_type: ConsentEventType,
+    pub token: Option<&'a str>,
+    pub source: &'a str,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentRecord {
+    pub list: String,
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub token: Option<String>,
+    pub source: String,
+}
+
+#[tracing::instrument(
+    name = "Record a consent event",
+    skip(executor, event, context),
+    fields(
+        subscriber_id = %event.subscriber_id,
+        event_type = event.event_type.as_str()
+    )
+)]
+pub async fn record_consent_event<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    event: &ConsentEvent<'_>,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            consent_event_id, subscriber_id, list_id, event_type, occurred_at,
+            ip_address, user_agent, token, source
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        event.subscriber_id,
+        event.list_id,
+        event.event_type.as_str(),
+        context.ip_address,
+        context.user_agent,
+        event.token,
+        event.source
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the consent log of a subscriber", skip(executor))]
+pub async fn get_consent_log<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT
+            l.slug AS list,
+            c.event_type,
+            c.occurred_at,
+            c.ip_address,
+            c.user_agent,
+            c.token,
+            c.source
+        FROM consent_events c
+        JOIN lists l ON l.list_id = c.list_id
+        WHERE c.subscriber_id = $1
+        ORDER BY c.occurred_at, c.consent_event_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+
+use super::{build_mime_message, Email, EmailTransport};
+
+// Writes every email as an `.eml` file in a local directory, handy for development
+pub struct FileSinkTransport {
+    sink: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileSinkTransport {
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(directory.as_ref())
+            .context("Failed to create the email sink directory")?;
+
+        Ok(Self {
+            sink: AsyncFileTransport::new(directory),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileSinkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let message = build_mime_message(email)?;
+        self.sink
+            .send(message)
+            .await
+            .context("Failed to write the email to the sink directory")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{email_client::EmailClient, types::SubscriberEmail};
+
+    #[tokio::test]
+    async fn test_emails_are_written_to_the_sink_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let email_client = EmailClient::new(sender, FileSinkTransport::new(&directory).unwrap());
+
+        email_client
+            .send_email(&recipient, "Subject", "<p>Html body</p>", "Text body")
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let message = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(message.contains("To: recipient@example.com"));
+        assert!(message.contains("Subject: Subject"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+mod file_sink;
+mod postmark;
+mod smtp;
+
+pub use file_sink::FileSinkTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+use anyhow::Context;
+use lettre::message::{
+    header::{HeaderName, HeaderValue},
+    MultiPart,
+};
+
+use crate::types::SubscriberEmail;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+// An email addressed to a single recipient, as part of a batch sent from the same sender
+pub struct BatchEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+pub struct Email<'a> {
+    pub sender: &'a SubscriberEmail,
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+// Delivery backend, so the application does not depend on a specific email provider
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<(), anyhow::Error>;
+
+    // One result per email, in the same order. Backends without a batch API send them one by one
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), anyhow::Error>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(email).await);
+        }
+        results
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<(), anyhow::Error> {
+        let email = Email {
+            sender: &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        };
+        self.transport.send(&email).await
+    }
+
+    // A failure to deliver one of the emails does not prevent the others from being sent
+    pub async fn send_email_batch(
+        &self,
+        batch: &[BatchEmail<'_>],
+    ) -> Vec<Result<(), anyhow::Error>> {
+        let emails: Vec<_> = batch
+            .iter()
+            .map(|email| Email {
+                sender: &self.sender,
+                recipient: email.recipient,
+                subject: email.subject,
+                html_content: email.html_content,
+                text_content: email.text_content,
+                headers: email.headers,
+            })
+            .collect();
+        self.transport.send_batch(&emails).await
+    }
+}
+
+// MIME representation shared by the backends that speak plain email
+fn build_mime_message(email: &Email<'_>) -> Result<lettre::Message, anyhow::Error> {
+    let mut builder = lettre::Message::builder()
+        .from(email.sender.as_ref().parse()?)
+        .to(email.recipient.as_ref().parse()?)
+        .subject(email.subject);
+    for header in email.headers {
+        let name = HeaderName::new_from_ascii(header.name.to_string())
+            .with_context(|| format!("Invalid email header name: {}", header.name))?;
+        builder = builder.raw_header(HeaderValue::new(name, header.value.to_string()));
+    }
+
+    builder
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_content.to_string(),
+            email.html_content.to_string(),
+        ))
+        .context("Failed to build the email message")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_message_contains_both_bodies_and_custom_headers() {
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://example.com>",
+        }];
+        let email = Email {
+            sender: &sender,
+            recipient: &recipient,
+            subject: "Subject",
+            html_content: "<p>Html body</p>",
+            text_content: "Text body",
+            headers: &headers,
+        };
+
+        let message = String::from_utf8(build_mime_message(&email).unwrap().formatted()).unwrap();
+
+        assert!(message.contains("List-Unsubscribe: <https://example.com>"));
+        assert!(message.contains("<p>Html body</p>"));
+        assert!(message.contains("Text body"));
+    }
+}
@@ -0,0 +1,432 @@
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{Email, EmailHeader, EmailTransport};
+
+// Maximum number of messages Postmark accepts in a single batch request
+const MAX_BATCH_SIZE: usize = 500;
+
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: SecretString,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+impl<'a> From<&'a Email<'a>> for EmailRequest<'a> {
+    fn from(email: &'a Email<'a>) -> Self {
+        Self {
+            from: email.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+            headers: email.headers,
+        }
+    }
+}
+
+// Postmark reports the outcome of every message of a batch, an error code of 0 means success
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchMessageResult {
+    error_code: i64,
+    message: String,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: SecretString,
+        http_client_timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder()
+            .timeout(http_client_timeout)
+            .build()
+            .expect("Unable to build HTTP client");
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = EmailRequest::from(email);
+        let _ = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn send_batch(&self, emails: &[Email<'_>]) -> Vec<Result<(), anyhow::Error>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results.into_iter().map(|result| {
+                    if result.error_code == 0 {
+                        Ok(())
+                    } else {
+                        Err(anyhow::anyhow!(
+                            "Postmark rejected the email (error code {}): {}",
+                            result.error_code,
+                            result.message
+                        ))
+                    }
+                })),
+                // The whole request failed, so none of its messages went out
+                Err(error) => results.extend(
+                    chunk
+                        .iter()
+                        .map(|_| Err(anyhow::anyhow!("The batch request failed: {:#}", error))),
+                ),
+            }
+        }
+        results
+    }
+}
+
+impl PostmarkTransport {
+    async fn send_chunk(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<BatchMessageResult>, anyhow::Error> {
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<_> = emails.iter().map(EmailRequest::from).collect();
+        let results: Vec<BatchMessageResult> = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        anyhow::ensure!(
+            results.len() == emails.len(),
+            "Postmark returned {} results for a batch of {} emails",
+            results.len(),
+            emails.len()
+        );
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use fake::{
+        faker::{
+            internet::en::SafeEmail,
+            lorem::en::{Paragraph, Sentence},
+        },
+        Fake, Faker,
+    };
+    use secrecy::Secret;
+    use wiremock::{
+        matchers::{any, body_partial_json, header, header_exists, method, path},
+        Match, Mock, MockServer, Respond, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        email_client::{BatchEmail, EmailClient},
+        types::SubscriberEmail,
+    };
+
+    struct EmailBodyMatcher;
+
+    impl Match for EmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn get_subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn get_content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn get_email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn get_email_client(base_url: String) -> EmailClient {
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        );
+        EmailClient::new(get_email(), transport)
+    }
+
+    #[tokio::test]
+    async fn test_email_client_fires_expected_http_request() {
+        let server = MockServer::start().await;
+
+        let email_client = get_email_client(server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(EmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Sending the actual email towards the mock server
+        let _ = email_client
+            .send_email(&get_email(), &get_subject(), &get_content(), &get_content())
+            .await;
+
+        // Once the mock server goes out of scope, it iterates and asserts all the Mocks
+        // Ours expects to receive exactly one request, and that's the assertion that is gonna do
+        // If this does not happen, the test fails
+    }
+
+    #[tokio::test]
+    async fn test_custom_headers_are_sent_to_postmark() {
+        let server = MockServer::start().await;
+
+        let email_client = get_email_client(server.uri());
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "Headers": [{"Name": "List-Unsubscribe", "Value": "<https://example.com>"}]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe",
+            value: "<https://example.com>",
+        }];
+        let response = email_client
+            .send_email_with_headers(
+                &get_email(),
+                &get_subject(),
+                &get_content(),
+                &get_content(),
+                &headers,
+            )
+            .await;
+
+        claims::assert_ok!(response);
+    }
+
+    #[tokio::test]
+    async fn test_send_email_works_on_200_response() {
+        let server = MockServer::start().await;
+
+        let email_client = get_email_client(server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Sending the actual email towards the mock server
+        let response = email_client
+            .send_email(&get_email(), &get_subject(), &get_content(), &get_content())
+            .await;
+
+        claims::assert_ok!(response);
+    }
+
+    #[tokio::test]
+    async fn test_send_email_fails_on_no_200_response() {
+        let server = MockServer::start().await;
+
+        let email_client = get_email_client(server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Sending the actual email towards the mock server
+        let response = email_client
+            .send_email(&get_email(), &get_subject(), &get_content(), &get_content())
+            .await;
+
+        claims::assert_err!(response);
+    }
+
+    #[tokio::test]
+    async fn test_send_email_fails_on_timeout() {
+        let server = MockServer::start().await;
+
+        let email_client = get_email_client(server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Sending the actual email towards the mock server
+        let response = email_client
+            .send_email(&get_email(), &get_subject(), &get_content(), &get_content())
+            .await;
+
+        claims::assert_err!(response);
+    }
+
+    // Answers every batch request with one result per message, rejecting the addresses in `rejected`
+    struct BatchResponder {
+        rejected: Vec<String>,
+    }
+
+    impl Respond for BatchResponder {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = body
+                .iter()
+                .map(|message| {
+                    let to = message["To"].as_str().unwrap();
+                    if self.rejected.iter().any(|rejected| rejected == to) {
+                        serde_json::json!({"ErrorCode": 300, "Message": "Invalid 'To' address.", "To": to})
+                    } else {
+                        serde_json::json!({"ErrorCode": 0, "Message": "OK", "To": to})
+                    }
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_individual_failures() {
+        let server = MockServer::start().await;
+        let email_client = get_email_client(server.uri());
+        let recipients: Vec<_> = (0..3).map(|_| get_email()).collect();
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(BatchResponder {
+                rejected: vec![recipients[1].as_ref().to_string()],
+            })
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let batch: Vec<_> = recipients
+            .iter()
+            .map(|recipient| BatchEmail {
+                recipient,
+                subject: "Subject",
+                html_content: "<p>Html body</p>",
+                text_content: "Text body",
+                headers: &[],
+            })
+            .collect();
+        let results = email_client.send_email_batch(&batch).await;
+
+        assert_eq!(results.len(), 3);
+        claims::assert_ok!(&results[0]);
+        claims::assert_err!(&results[1]);
+        claims::assert_ok!(&results[2]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_is_split_in_requests_of_at_most_500_emails() {
+        let server = MockServer::start().await;
+        let email_client = get_email_client(server.uri());
+        let recipient = get_email();
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(BatchResponder { rejected: vec![] })
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let batch: Vec<_> = (0..MAX_BATCH_SIZE + 1)
+            .map(|_| BatchEmail {
+                recipient: &recipient,
+                subject: "Subject",
+                html_content: "<p>Html body</p>",
+                text_content: "Text body",
+                headers: &[],
+            })
+            .collect();
+        let results = email_client.send_email_batch(&batch).await;
+
+        assert_eq!(results.len(), MAX_BATCH_SIZE + 1);
+        assert!(results.iter().all(|result| result.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_request_fails_all_its_emails() {
+        let server = MockServer::start().await;
+        let email_client = get_email_client(server.uri());
+        let recipients: Vec<_> = (0..2).map(|_| get_email()).collect();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let batch: Vec<_> = recipients
+            .iter()
+            .map(|recipient| BatchEmail {
+                recipient,
+                subject: "Subject",
+                html_content: "<p>Html body</p>",
+                text_content: "Text body",
+                headers: &[],
+            })
+            .collect();
+        let results = email_client.send_email_batch(&batch).await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.is_err()));
+    }
+}
@@ -0,0 +1,43 @@
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+use secrecy::{ExposeSecret, SecretString};
+
+use super::{build_mime_message, Email, EmailTransport};
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    // The connection is upgraded with STARTTLS before authenticating
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: String,
+        password: SecretString,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+            .port(port)
+            .credentials(Credentials::new(
+                username,
+                password.expose_secret().to_string(),
+            ))
+            .timeout(Some(timeout))
+            .build();
+
+        Ok(Self { mailer })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let message = build_mime_message(email)?;
+        self.mailer.send(message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use anyhow::Context;
+use sqlx::{
+    postgres::{PgHasArrayType, PgTypeInfo},
+    Executor, PgPool, Postgres, Transaction,
+};
+use uuid::Uuid;
+
+use crate::types::IdempotencyKey;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+impl PgHasArrayType for HeaderPairRecord {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_header_pair")
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+    // The transaction holds the lock on the idempotency row until the response is saved
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(HttpResponse),
+}
+
+// Concurrent requests with the same key block on the INSERT until the first one commits, so
+// duplicates are serialized and end up replaying the saved response
+#[tracing::instrument(name = "Try to start processing an idempotent request", skip(db_pool))]
+pub async fn try_processing(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    );
+    let n_inserted_rows = db_transaction
+        .execute(query)
+        .await
+        .context("Failed to insert the idempotency key.")?
+        .rows_affected();
+
+    if n_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(db_transaction));
+    }
+
+    let saved_response = get_saved_response(db_pool, idempotency_key, user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, but we didn't find it."))?;
+
+    Ok(NextAction::ReturnSavedResponse(saved_response))
+}
+
+#[tracing::instrument(name = "Get saved response", skip(db_pool))]
+async fn get_saved_response(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the saved response.")?;
+
+    let Some(row) = saved_response else {
+        return Ok(None);
+    };
+
+    let status_code = StatusCode::from_u16(row.response_status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in row.response_headers {
+        response.append_header((name, value));
+    }
+
+    Ok(Some(response.body(row.response_body)))
+}
+
+#[tracing::instrument(
+    name = "Save response for an idempotent request",
+    skip(db_transaction, http_response)
+)]
+pub async fn save_response(
+    mut db_transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    // The body is consumed to be stored, so the response has to be rebuilt before returning it
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context("Failed to read the response body.")?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    let query = sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    );
+    db_transaction
+        .execute(query)
+        .await
+        .context("Failed to save the response.")?;
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save the response.")?;
+
+    let http_response = response_head.set_body(body).map_into_boxed_body();
+
+    Ok(http_response)
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::Duration,
+};
+
+use sqlx::{Executor, PgPool, Postgres};
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    email_client::{BatchEmail, EmailClient, EmailHeader},
+    startup::Application,
+    tracking::add_tracking,
+    types::{IssueTemplate, RecipientDetails, SubscriberEmail},
+};
+
+// A task that keeps failing is dropped after this many retries
+const MAX_RETRIES: i16 = 5;
+// Base delay between retries, doubled on every failed attempt
+const RETRY_BACKOFF_SECONDS: f64 = 30.0;
+// Largest number of tasks sent in one request, the most Postmark accepts in a batch
+const BATCH_SIZE: i64 = 500;
+// How long claimed tasks are hidden from other workers while their batch is being sent
+const LEASE_SECONDS: f64 = 600.0;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+// A task whose email is ready to be sent
+struct Delivery {
+    task: DeliveryTask,
+    email: SubscriberEmail,
+    personalized: PersonalizedIssue,
+    list_unsubscribe: String,
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    list_slug: String,
+}
+
+struct Recipient {
+    subscriber_id: Uuid,
+    name: String,
+    unsubscribe_token: String,
+}
+
+// What a recipient gets: placeholders filled in and the unsubscribe link appended
+pub(crate) struct PersonalizedIssue {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+pub(crate) fn personalize_issue(
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+    recipient: &RecipientDetails,
+) -> Result<PersonalizedIssue, String> {
+    let title = IssueTemplate::parse(title)?.render_text(recipient);
+    let html_content = format!(
+        "{}<p><a href=\"{}\">Unsubscribe</a></p>",
+        IssueTemplate::parse(html_content)?.render_html(recipient),
+        recipient.unsubscribe_url
+    );
+    let text_content = format!(
+        "{}\n\nTo unsubscribe visit {}",
+        IssueTemplate::parse(text_content)?.render_text(recipient),
+        recipient.unsubscribe_url
+    );
+
+    Ok(PersonalizedIssue {
+        title,
+        html_content,
+        text_content,
+    })
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let db_pool = Application::get_db_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+
+    worker_loop(db_pool, email_client, configuration.application.base_url).await
+}
+
+async fn worker_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(&db_pool, &email_client, &base_url).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+// Delivers a batch of due tasks with a single request to the email backend. Every task is then
+// deleted or retried according to the outcome of its own email
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_task(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let tasks = claim_tasks(db_pool).await?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", tasks.len());
+
+    let mut issues = HashMap::new();
+    let mut deliveries = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        // A task that cannot be prepared must not hold back the rest of the batch
+        match prepare_delivery(db_pool, &mut issues, base_url, task).await {
+            Ok(Some(delivery)) => deliveries.push(delivery),
+            Ok(None) => {}
+            Err((task, error)) => record_failure(db_pool, &task, &error).await?,
+        }
+    }
+
+    let headers: Vec<_> = deliveries
+        .iter()
+        .map(|delivery| {
+            [
+                EmailHeader {
+                    name: "List-Unsubscribe",
+                    value: &delivery.list_unsubscribe,
+                },
+                EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                },
+            ]
+        })
+        .collect();
+    let batch: Vec<_> = deliveries
+        .iter()
+        .zip(&headers)
+        .map(|(delivery, headers)| BatchEmail {
+            recipient: &delivery.email,
+            subject: &delivery.personalized.title,
+            html_content: &delivery.personalized.html_content,
+            text_content: &delivery.personalized.text_content,
+            headers,
+        })
+        .collect();
+    let results = if batch.is_empty() {
+        Vec::new()
+    } else {
+        email_client.send_email_batch(&batch).await
+    };
+
+    // The emails are out, their outcomes are recorded right away so they are not sent twice
+    let mut db_transaction = db_pool.begin().await?;
+    for (delivery, result) in deliveries.iter().zip(results) {
+        match result {
+            Ok(()) => delete_task(&mut *db_transaction, &delivery.task).await?,
+            Err(error) => record_failure(&mut *db_transaction, &delivery.task, &error).await?,
+        }
+    }
+    db_transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+// Builds the email of a task. Tasks that have nothing to deliver anymore are deleted on the way,
+// failed ones are handed back to be retried
+async fn prepare_delivery(
+    db_pool: &PgPool,
+    issues: &mut HashMap<Uuid, NewsletterIssue>,
+    base_url: &str,
+    task: DeliveryTask,
+) -> Result<Option<Delivery>, (DeliveryTask, anyhow::Error)> {
+    let recipient = match get_recipient(db_pool, &task).await {
+        Ok(recipient) => recipient,
+        Err(error) => return Err((task, error)),
+    };
+    // Subscribers might have left the list since the issue was published
+    let Some(recipient) = recipient else {
+        tracing::info!(
+            newsletter_issue_id = %task.newsletter_issue_id,
+            subscriber_email = %task.subscriber_email,
+            "Skipping a subscriber that is no longer confirmed"
+        );
+        return match delete_task(db_pool, &task).await {
+            Ok(()) => Ok(None),
+            Err(error) => Err((task, error)),
+        };
+    };
+    let email = match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => email,
+        Err(error) => {
+            tracing::error!(
+                error.message = %error,
+                newsletter_issue_id = %task.newsletter_issue_id,
+                subscriber_email = %task.subscriber_email,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+            return match delete_task(db_pool, &task).await {
+                Ok(()) => Ok(None),
+                Err(error) => Err((task, error)),
+            };
+        }
+    };
+
+    let issue = match issues.entry(task.newsletter_issue_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => match get_issue(db_pool, task.newsletter_issue_id).await {
+            Ok(issue) => entry.insert(issue),
+            Err(error) => return Err((task, error)),
+        },
+    };
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}&list={}",
+        base_url, recipient.unsubscribe_token, issue.list_slug
+    );
+    // The tracking tokens are committed before sending, the links in the email must resolve
+    let html_content = match track_links(db_pool, &task, &recipient, base_url, issue).await {
+        Ok(html_content) => html_content,
+        Err(error) => return Err((task, error)),
+    };
+    let details = RecipientDetails {
+        name: &recipient.name,
+        email: &task.subscriber_email,
+        unsubscribe_url: &unsubscribe_link,
+    };
+    let personalized =
+        match personalize_issue(&issue.title, &html_content, &issue.text_content, &details) {
+            Ok(personalized) => personalized,
+            Err(error) => return Err((task, anyhow::anyhow!(error))),
+        };
+    // RFC 8058 one-click unsubscribe, mail clients POST to the link on their own
+    let list_unsubscribe = format!("<{}>", unsubscribe_link);
+
+    Ok(Some(Delivery {
+        task,
+        email,
+        personalized,
+        list_unsubscribe,
+    }))
+}
+
+async fn track_links(
+    db_pool: &PgPool,
+    task: &DeliveryTask,
+    recipient: &Recipient,
+    base_url: &str,
+    issue: &NewsletterIssue,
+) -> Result<String, anyhow::Error> {
+    let mut db_transaction = db_pool.begin().await?;
+    let html_content = add_tracking(
+        &mut db_transaction,
+        task.newsletter_issue_id,
+        recipient.subscriber_id,
+        base_url,
+        &issue.html_content,
+    )
+    .await?;
+    db_transaction.commit().await?;
+
+    Ok(html_content)
+}
+
+// Claimed tasks are pushed back in the queue for the duration of the lease, so other workers
+// leave them alone without a transaction being held open while the emails are sent. A worker
+// that dies before recording the outcomes only delays them until the lease runs out
+#[tracing::instrument(skip_all)]
+async fn claim_tasks(db_pool: &PgPool) -> Result<Vec<DeliveryTask>, anyhow::Error> {
+    let tasks = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = now() + make_interval(secs => $2)
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT $1
+        )
+        RETURNING newsletter_issue_id, subscriber_email, n_retries
+        "#,
+        BATCH_SIZE,
+        LEASE_SECONDS
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(tasks)
+}
+
+// Retries the task later, or drops it once it ran out of retries
+async fn record_failure<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    task: &DeliveryTask,
+    error: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    if task.n_retries < MAX_RETRIES {
+        tracing::warn!(
+            error.cause_chain = ?error,
+            error.message = %error,
+            newsletter_issue_id = %task.newsletter_issue_id,
+            subscriber_email = %task.subscriber_email,
+            n_retries = task.n_retries,
+            "Failed to deliver issue to a confirmed subscriber. Scheduling a retry.",
+        );
+        return schedule_retry(executor, task).await;
+    }
+    tracing::error!(
+        error.cause_chain = ?error,
+        error.message = %error,
+        newsletter_issue_id = %task.newsletter_issue_id,
+        subscriber_email = %task.subscriber_email,
+        "Failed to deliver issue to a confirmed subscriber. Giving up after {} retries.",
+        MAX_RETRIES,
+    );
+    delete_task(executor, task).await
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    executor.execute(query).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let backoff_seconds = RETRY_BACKOFF_SECONDS * 2f64.powi(task.n_retries.into());
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        backoff_seconds
+    );
+    executor.execute(query).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT i.title, i.text_content, i.html_content, l.slug AS list_slug
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(issue)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    db_pool: &PgPool,
+    task: &DeliveryTask,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT s.id AS subscriber_id, s.name, s.unsubscribe_token
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = m.list_id
+        WHERE
+            s.email = $1 AND
+            i.newsletter_issue_id = $2 AND
+            m.status = 'confirmed' AND
+            s.suppression IS NULL
+        "#,
+        task.subscriber_email,
+        task.newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(recipient)
+}
@@ -1,6 +1,23 @@
+pub mod api_tokens;
+pub mod authentication;
+pub mod authorization;
 pub mod configuration;
+pub mod consent;
 pub mod email_client;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod login_attempts;
+pub mod mailing_lists;
+pub mod markdown;
+pub mod newsletter_scheduler;
+pub mod personal_data;
+pub mod rate_limiting;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
 pub mod telemetry;
+pub mod totp;
+pub mod tracking;
+pub mod two_factor;
 pub mod types;
@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Failed attempts tolerated before a lockout. A single ip may be shared by several publishers
+const USERNAME_FREE_FAILURES: i32 = 3;
+const IP_FREE_FAILURES: i32 = 10;
+// The first lockout lasts BASE_LOCKOUT, every further failure doubles it up to MAX_LOCKOUT
+const BASE_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+// Failures older than this are forgotten, it must outlast MAX_LOCKOUT
+const FAILURE_MEMORY: Duration = Duration::from_secs(24 * 60 * 60);
+// Share of attempts that also delete the rows of forgotten failures
+const CLEANUP_PROBABILITY: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LockoutScope {
+    Username,
+    Ip,
+}
+
+impl LockoutScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutScope::Username => "username",
+            LockoutScope::Ip => "ip",
+        }
+    }
+
+    fn free_failures(&self) -> i32 {
+        match self {
+            LockoutScope::Username => USERNAME_FREE_FAILURES,
+            LockoutScope::Ip => IP_FREE_FAILURES,
+        }
+    }
+}
+
+// Who a login attempt is counted against
+pub struct LoginAttempt<'a> {
+    pub username: &'a str,
+    pub client_ip: Option<&'a str>,
+}
+
+impl LoginAttempt<'_> {
+    fn subjects(&self) -> Vec<(LockoutScope, &str)> {
+        let mut subjects = vec![(LockoutScope::Username, self.username)];
+        if let Some(client_ip) = self.client_ip {
+            subjects.push((LockoutScope::Ip, client_ip));
+        }
+        subjects
+    }
+}
+
+fn lockout_duration(failures: i32, free_failures: i32) -> Option<Duration> {
+    let doublings = failures.checked_sub(free_failures)?;
+    let doublings = u32::try_from(doublings).ok()?;
+    let lockout = 2u32
+        .checked_pow(doublings)
+        .and_then(|factor| BASE_LOCKOUT.checked_mul(factor))
+        .unwrap_or(MAX_LOCKOUT);
+
+    Some(lockout.min(MAX_LOCKOUT))
+}
+
+pub enum LockoutCheck {
+    Allowed(PendingAttempt),
+    LockedOut(Duration),
+}
+
+// An attempt that passed the lockout check. It is counted as a failure until it turns out to
+// be a success, so concurrent guesses cannot all pass the check before any of them is counted
+pub struct PendingAttempt {
+    username: String,
+    charges: Vec<Charge>,
+}
+
+// The failure counted against a username or ip, with what is needed to take it back
+struct Charge {
+    scope: LockoutScope,
+    subject: String,
+    failures: i32,
+    lockout: Option<Duration>,
+    locked_until: Option<DateTime<Utc>>,
+    previous_locked_until: Option<DateTime<Utc>>,
+}
+
+// Turns the attempt away when its username or ip is locked out, otherwise counts it as a failure
+// right away. The rows are only locked for the check, not while the credentials are verified
+#[tracing::instrument(name = "Check login lockout", skip(db_pool, attempt))]
+pub async fn begin_attempt(
+    db_pool: &PgPool,
+    attempt: &LoginAttempt<'_>,
+) -> Result<LockoutCheck, anyhow::Error> {
+    if rand::thread_rng().gen_bool(CLEANUP_PROBABILITY) {
+        sqlx::query!(
+            "DELETE FROM failed_login_attempts WHERE last_failure_at <= now() - make_interval(secs => $1)",
+            FAILURE_MEMORY.as_secs_f64()
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to delete the forgotten failed logins.")?;
+    }
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let now = Utc::now();
+    let mut charges = Vec::new();
+    for (scope, subject) in attempt.subjects() {
+        // New subjects start without failures, so that there is a row to lock
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO failed_login_attempts (scope, subject, failures, last_failure_at)
+            VALUES ($1, $2, 0, now())
+            ON CONFLICT (scope, subject) DO UPDATE SET scope = EXCLUDED.scope
+            RETURNING failures, last_failure_at, locked_until
+            "#,
+            scope.as_str(),
+            subject
+        )
+        .fetch_one(&mut *db_transaction)
+        .await
+        .context("Failed to look up the login lockouts.")?;
+
+        if let Some(lockout) = row
+            .locked_until
+            .and_then(|locked_until| (locked_until - now).to_std().ok())
+        {
+            return Ok(LockoutCheck::LockedOut(lockout));
+        }
+        let failures = if now - row.last_failure_at >= chrono::Duration::from_std(FAILURE_MEMORY)? {
+            1
+        } else {
+            row.failures + 1
+        };
+        let lockout = lockout_duration(failures, scope.free_failures());
+        let locked_until = match lockout {
+            Some(lockout) => Some(now + chrono::Duration::from_std(lockout)?),
+            None => row.locked_until,
+        };
+        sqlx::query!(
+            r#"
+            UPDATE failed_login_attempts
+            SET failures = $3, last_failure_at = $4, locked_until = $5
+            WHERE scope = $1 AND subject = $2
+            "#,
+            scope.as_str(),
+            subject,
+            failures,
+            now,
+            locked_until
+        )
+        .execute(&mut *db_transaction)
+        .await
+        .context("Failed to record a failed login.")?;
+
+        charges.push(Charge {
+            scope,
+            subject: subject.to_string(),
+            failures,
+            lockout,
+            locked_until,
+            previous_locked_until: row.locked_until,
+        });
+    }
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to record a login attempt.")?;
+
+    Ok(LockoutCheck::Allowed(PendingAttempt {
+        username: attempt.username.to_string(),
+        charges,
+    }))
+}
+
+impl PendingAttempt {
+    // The failure is already counted, only the lockouts it caused are left to report
+    pub fn fail(self) {
+        for charge in self.charges {
+            let Some(lockout) = charge.lockout else {
+                continue;
+            };
+            tracing::warn!(
+                security.event = "login_lockout",
+                security.scope = charge.scope.as_str(),
+                security.subject = %charge.subject,
+                security.failures = charge.failures,
+                security.lockout_seconds = lockout.as_secs(),
+                "Locked out repeated failed logins"
+            );
+        }
+    }
+
+    // A successful login starts the username over, the ip keeps its previous count
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn succeed(self, db_pool: &PgPool) -> Result<(), anyhow::Error> {
+        for charge in &self.charges {
+            if let LockoutScope::Ip = charge.scope {
+                take_back(db_pool, charge).await?;
+            }
+        }
+        clear_failed_logins(db_pool, &self.username).await
+    }
+
+    // The attempt was neither a success nor a failure, like a password waiting for its
+    // second factor
+    #[tracing::instrument(name = "Take back login attempt", skip(self))]
+    pub async fn cancel(self, db_pool: &PgPool) -> Result<(), anyhow::Error> {
+        for charge in &self.charges {
+            take_back(db_pool, charge).await?;
+        }
+
+        Ok(())
+    }
+}
+
+// Lockouts set by later failures in the meantime are kept
+async fn take_back(db_pool: &PgPool, charge: &Charge) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE failed_login_attempts
+        SET
+            failures = GREATEST(failures - 1, 0),
+            locked_until = CASE
+                WHEN locked_until = $3 THEN $4
+                ELSE locked_until
+            END
+        WHERE scope = $1 AND subject = $2
+        "#,
+        charge.scope.as_str(),
+        charge.subject,
+        charge.locked_until,
+        charge.previous_locked_until
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to take back a login attempt.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Clear failed logins", skip(db_pool))]
+async fn clear_failed_logins(db_pool: &PgPool, username: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM failed_login_attempts WHERE scope = 'username' AND subject = $1",
+        username
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to clear the failed logins.")?;
+
+    Ok(())
+}
+
+// Lifts the lockout of a user account. Returns false when there is no such user
+#[tracing::instrument(name = "Unlock user", skip(db_pool))]
+pub async fn unlock_user(db_pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let Some(row) = sqlx::query!("SELECT username FROM users WHERE user_id = $1", user_id)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed to look up the user to unlock.")?
+    else {
+        return Ok(false);
+    };
+
+    clear_failed_logins(db_pool, &row.username).await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_below_the_threshold_are_not_locked_out() {
+        assert_eq!(lockout_duration(1, 3), None);
+        assert_eq!(lockout_duration(2, 3), None);
+    }
+
+    #[test]
+    fn test_lockouts_double_with_every_failure() {
+        assert_eq!(lockout_duration(3, 3), Some(BASE_LOCKOUT));
+        assert_eq!(lockout_duration(4, 3), Some(BASE_LOCKOUT * 2));
+        assert_eq!(lockout_duration(5, 3), Some(BASE_LOCKOUT * 4));
+    }
+
+    #[test]
+    fn test_lockouts_are_capped() {
+        assert_eq!(lockout_duration(20, 3), Some(MAX_LOCKOUT));
+        assert_eq!(lockout_duration(i32::MAX, 3), Some(MAX_LOCKOUT));
+    }
+}
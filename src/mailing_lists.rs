@@ -0,0 +1,20 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::types::ListSlug;
+
+#[tracing::instrument(name = "Get list id from slug", skip(db_pool))]
+pub async fn get_list_id(db_pool: &PgPool, slug: &ListSlug) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug.as_ref())
+        .fetch_optional(db_pool)
+        .await?;
+
+    Ok(row.map(|r| r.list_id))
+}
+
+// Requests that do not name a list target the default one
+pub fn parse_list_slug(slug: Option<String>) -> Result<ListSlug, String> {
+    slug.map(ListSlug::parse)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
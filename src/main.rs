@@ -1,14 +1,49 @@
-use zero2prod::{configuration, startup::Application, telemetry};
+use std::fmt::{Debug, Display};
+
+use tokio::task::JoinError;
+use zero2prod::{
+    configuration, issue_delivery_worker::run_worker_until_stopped, startup::Application, telemetry,
+};
 
 #[actix_web::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> Result<(), anyhow::Error> {
     let configuration = configuration::get_configuration().expect("Failed to read configuration");
 
     telemetry::Telemetry::create("zero2prod".into(), "info".into(), std::io::stdout);
 
-    let application = Application::build(configuration).await?;
+    let application = Application::build(configuration.clone()).await?;
+
+    let application_task = tokio::spawn(application.run_application());
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
 
-    application.run_application().await?;
+    tokio::select! {
+        outcome = application_task => report_exit("API", outcome),
+        outcome = worker_task => report_exit("Background worker", outcome),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(error)) => {
+            tracing::error!(
+                error.cause_chain = ?error,
+                error.message = %error,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(error) => {
+            tracing::error!(
+                error.cause_chain = ?error,
+                error.message = %error,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
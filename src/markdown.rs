@@ -0,0 +1,197 @@
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+}
+
+// Placeholders like `{{ unsubscribe_url }}` are filled in for every recipient after rendering.
+// Markdown would percent-encode their braces in link targets, so they are swapped for plain
+// markers while rendering and put back afterwards
+fn protect_placeholders(markdown: &str) -> (String, Vec<(String, &str)>) {
+    let mut prefix = String::from("placeholder");
+    while markdown.contains(&prefix) {
+        prefix.insert(0, 'x');
+    }
+    let mut protected = String::with_capacity(markdown.len());
+    let mut placeholders = Vec::new();
+    let mut rest = markdown;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let end = start + length + 2;
+        let marker = format!("{}{}end", prefix, placeholders.len());
+        protected.push_str(&rest[..start]);
+        protected.push_str(&marker);
+        placeholders.push((marker, &rest[start..end]));
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    (protected, placeholders)
+}
+
+fn restore_placeholders(rendered: String, placeholders: &[(String, &str)]) -> String {
+    placeholders
+        .iter()
+        .fold(rendered, |rendered, (marker, placeholder)| {
+            rendered.replace(marker, placeholder)
+        })
+}
+
+pub fn render_html(markdown: &str) -> String {
+    let (markdown, placeholders) = protect_placeholders(markdown);
+    let mut html_output = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut html_output, parser(&markdown));
+    restore_placeholders(html_output, &placeholders)
+}
+
+// Plain text version of the same source, links keep their target next to the text and
+// raw html is dropped
+pub fn render_text(markdown: &str) -> String {
+    let (markdown, placeholders) = protect_placeholders(markdown);
+    let mut text = String::with_capacity(markdown.len());
+    // Destination and start position of the text of the links being rendered
+    let mut links: Vec<(String, usize)> = Vec::new();
+    // Next number of every ordered list being rendered, None for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+
+    for event in parser(&markdown) {
+        match event {
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            Event::Start(Tag::List(first_number)) => {
+                end_line(&mut text);
+                lists.push(first_number);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) => end_line(&mut text),
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                links.push((dest_url.to_string(), text.len()))
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some((url, start)) = links.pop() {
+                    // Autolinks already show their target
+                    if text[start..] != url {
+                        text.push_str(&format!(" ({})", url));
+                    }
+                }
+            }
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Heading(_)) => text.push_str("\n\n"),
+            Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::TableRow) => end_line(&mut text),
+            Event::End(TagEnd::TableHead) => text.push('\n'),
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            _ => {}
+        }
+    }
+
+    restore_placeholders(collapse_blank_lines(&text), &placeholders)
+}
+
+fn end_line(text: &mut String) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+fn collapse_blank_lines(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.trim().lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        collapsed.push_str(line);
+        collapsed.push('\n');
+    }
+    collapsed.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_is_rendered_to_html() {
+        let html = render_html("# Title\n\nSome *emphasis* and a [link](https://example.com).");
+
+        assert_eq!(
+            html,
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em> and a <a href=\"https://example.com\">link</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_text_keeps_structure_without_markup() {
+        let markdown = "# Title\n\nSome *emphasis* and `code`.\n\n\n\nAnother paragraph.";
+
+        assert_eq!(
+            render_text(markdown),
+            "Title\n\nSome emphasis and code.\n\nAnother paragraph."
+        );
+    }
+
+    #[test]
+    fn test_text_links_show_their_target() {
+        let markdown = "Read the [docs](https://example.com/docs) or <https://example.com>.";
+
+        assert_eq!(
+            render_text(markdown),
+            "Read the docs (https://example.com/docs) or https://example.com."
+        );
+    }
+
+    #[test]
+    fn test_text_lists_are_marked_and_numbered() {
+        let markdown = "Intro\n\n- one\n- two\n  1. first\n  2. second\n\nOutro";
+
+        assert_eq!(
+            render_text(markdown),
+            "Intro\n\n- one\n- two\n  1. first\n  2. second\n\nOutro"
+        );
+    }
+
+    #[test]
+    fn test_placeholders_in_links_are_left_untouched() {
+        let markdown =
+            "Hi {{ name }}, [leave]({{unsubscribe_url}}) or [stay]({{ unsubscribe_url }})";
+
+        assert_eq!(
+            render_html(markdown),
+            "<p>Hi {{ name }}, <a href=\"{{unsubscribe_url}}\">leave</a> or <a href=\"{{ unsubscribe_url }}\">stay</a></p>\n"
+        );
+        assert_eq!(
+            render_text(markdown),
+            "Hi {{ name }}, leave ({{unsubscribe_url}}) or stay ({{ unsubscribe_url }})"
+        );
+    }
+
+    #[test]
+    fn test_text_drops_raw_html() {
+        assert_eq!(render_text("Hello <b>there</b>"), "Hello there");
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::routes::enqueue_delivery_tasks;
+
+// How often the scheduler looks for issues that are due
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+pub async fn run_scheduler_until_stopped(db_pool: PgPool) {
+    loop {
+        // Failures are already logged, the next poll retries the same issues
+        let _ = publish_due_issues(&db_pool).await;
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+// Flips every due issue to published and enqueues its deliveries in a single transaction, so an
+// issue cancelled concurrently is either sent in full or not at all
+#[tracing::instrument(skip_all, err)]
+pub async fn publish_due_issues(db_pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut db_transaction = db_pool.begin().await?;
+    let due_issues = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now()
+        WHERE status = 'scheduled' AND send_at <= now()
+        RETURNING newsletter_issue_id
+        "#
+    )
+    .fetch_all(&mut *db_transaction)
+    .await?;
+
+    for issue in &due_issues {
+        tracing::info!(
+            newsletter_issue_id = %issue.newsletter_issue_id,
+            "Publishing a scheduled newsletter issue"
+        );
+        enqueue_delivery_tasks(&mut db_transaction, issue.newsletter_issue_id).await?;
+    }
+    db_transaction.commit().await?;
+
+    Ok(due_issues.len())
+}
@@ -0,0 +1,340 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::consent::{get_consent_log, ConsentRecord};
+
+// Everything stored about a subscriber, as handed over on a data access request
+#[derive(serde::Serialize)]
+pub struct PersonalDataExport {
+    subscriber: SubscriberRecord,
+    memberships: Vec<MembershipRecord>,
+    tags: Vec<String>,
+    subscription_tokens: Vec<SubscriptionTokenRecord>,
+    consent_events: Vec<ConsentRecord>,
+    deliveries: Vec<DeliveryRecord>,
+    pending_deliveries: Vec<PendingDeliveryRecord>,
+    tracking_events: Vec<TrackingEventRecord>,
+    email_events: Vec<EmailEventRecord>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberRecord {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    unsubscribe_token: String,
+}
+
+#[derive(serde::Serialize)]
+struct MembershipRecord {
+    list: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriptionTokenRecord {
+    subscription_token: String,
+    list: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDeliveryRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    n_retries: i16,
+    execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct TrackingEventRecord {
+    newsletter_issue_id: Uuid,
+    // None for opens
+    url: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailEventRecord {
+    record_type: String,
+    payload: serde_json::Value,
+    received_at: DateTime<Utc>,
+}
+
+// Keyed so that the hash of a known address cannot be recomputed without the secret
+pub fn tombstone_hash(secret: &SecretString, email: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(email.trim().to_lowercase().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[tracing::instrument(
+    name = "Export the personal data of a subscriber",
+    skip(db_pool, email)
+)]
+pub async fn export_personal_data(
+    db_pool: &PgPool,
+    email: &str,
+) -> Result<Option<PersonalDataExport>, anyhow::Error> {
+    let Some(subscriber) = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id AS subscriber_id, email, name, subscribed_at, unsubscribe_token
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch the subscriber.")?
+    else {
+        return Ok(None);
+    };
+    let subscriber_id = subscriber.subscriber_id;
+
+    let memberships = sqlx::query_as!(
+        MembershipRecord,
+        r#"
+        SELECT l.slug AS list, m.status, m.subscribed_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the list memberships.")?;
+    let tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the tags.")?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+        SELECT t.subscription_token, l.slug AS list, t.created_at, t.expires_at
+        FROM subscription_tokens t
+        JOIN lists l ON l.list_id = t.list_id
+        WHERE t.subscriber_id = $1
+        ORDER BY t.created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the subscription tokens.")?;
+    let consent_events = get_consent_log(db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the consent log.")?;
+    // Every delivered email gets an open pixel token, so those make up the delivery history
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT DISTINCT i.newsletter_issue_id, i.title, i.published_at
+        FROM tracking_tokens t
+        JOIN newsletter_issues i ON i.newsletter_issue_id = t.newsletter_issue_id
+        WHERE t.subscriber_id = $1 AND t.link_id IS NULL
+        ORDER BY i.published_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the delivery history.")?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDeliveryRecord,
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY q.execute_after
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the pending deliveries.")?;
+    let tracking_events = sqlx::query_as!(
+        TrackingEventRecord,
+        r#"
+        SELECT t.newsletter_issue_id, l.url AS "url?", e.occurred_at
+        FROM tracking_events e
+        JOIN tracking_tokens t ON t.tracking_token = e.tracking_token
+        LEFT JOIN issue_links l ON l.link_id = t.link_id
+        WHERE t.subscriber_id = $1
+        ORDER BY e.occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the tracking events.")?;
+    let email_events = sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+        SELECT record_type, payload, received_at
+        FROM email_events
+        WHERE email = $1
+        ORDER BY received_at
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to fetch the email events.")?;
+
+    Ok(Some(PersonalDataExport {
+        subscriber,
+        memberships,
+        tags,
+        subscription_tokens,
+        consent_events,
+        deliveries,
+        pending_deliveries,
+        tracking_events,
+        email_events,
+    }))
+}
+
+// Removes the subscriber and everything tied to them in one go, leaving only a tombstone.
+// Returns false when there is nobody to erase
+#[tracing::instrument(name = "Erase the personal data of a subscriber", skip_all)]
+pub async fn erase_personal_data(
+    db_pool: &PgPool,
+    email: &str,
+    tombstone_secret: &SecretString,
+) -> Result<bool, anyhow::Error> {
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let Some(subscriber) = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email = $1 FOR UPDATE",
+        email
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await
+    .context("Failed to fetch the subscriber.")?
+    else {
+        return Ok(false);
+    };
+
+    let subscriber_id = subscriber.id;
+    let queries = [
+        sqlx::query!(
+            r#"
+            DELETE FROM tracking_events
+            WHERE tracking_token IN (
+                SELECT tracking_token FROM tracking_tokens WHERE subscriber_id = $1
+            )
+            "#,
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM tracking_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM list_memberships WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        // Consent events go along with the subscriber, through ON DELETE CASCADE
+        sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id),
+        sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+            email
+        ),
+        sqlx::query!("DELETE FROM email_events WHERE email = $1", email),
+    ];
+    for query in queries {
+        db_transaction
+            .execute(query)
+            .await
+            .context("Failed to delete the data of the subscriber.")?;
+    }
+
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (tombstone_hash, erased_at)
+        VALUES ($1, now())
+        ON CONFLICT (tombstone_hash) DO UPDATE SET erased_at = EXCLUDED.erased_at
+        "#,
+        tombstone_hash(tombstone_secret, email)
+    );
+    db_transaction
+        .execute(query)
+        .await
+        .context("Failed to store the tombstone of the subscriber.")?;
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::new(value.to_string())
+    }
+
+    #[test]
+    fn test_tombstone_hash_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            tombstone_hash(&secret("key"), "ursula@example.com"),
+            tombstone_hash(&secret("key"), " Ursula@Example.com ")
+        );
+    }
+
+    #[test]
+    fn test_tombstone_hash_does_not_contain_the_email() {
+        let hash = tombstone_hash(&secret("key"), "ursula@example.com");
+
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains("ursula"));
+    }
+
+    #[test]
+    fn test_tombstone_hash_depends_on_the_secret() {
+        assert_ne!(
+            tombstone_hash(&secret("key"), "ursula@example.com"),
+            tombstone_hash(&secret("another key"), "ursula@example.com")
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use super::{CounterStore, WindowState};
+
+// Expired windows are dropped at most this often, so the sweep is not paid on every hit
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// Hard cap on the keys being tracked. New keys are limited until the next sweep makes room,
+// forgetting live windows instead would let a flood of keys reset everybody's counters
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+// Counters of a single application instance
+#[derive(Default)]
+pub struct InMemoryCounterStore {
+    windows: Mutex<Windows>,
+}
+
+#[derive(Default)]
+struct Windows {
+    states: HashMap<String, WindowState>,
+    next_sweep: DateTime<Utc>,
+}
+
+#[async_trait::async_trait]
+impl CounterStore for InMemoryCounterStore {
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowState, anyhow::Error> {
+        let now = Utc::now();
+        let mut windows = self
+            .windows
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The rate limit counters are poisoned."))?;
+        if windows.next_sweep <= now {
+            windows.states.retain(|_, state| state.expires_at > now);
+            windows.next_sweep = now + SWEEP_INTERVAL;
+        }
+        if windows.states.len() >= MAX_TRACKED_KEYS && !windows.states.contains_key(key) {
+            return Ok(WindowState {
+                hits: u32::MAX,
+                expires_at: windows.next_sweep,
+            });
+        }
+
+        let state = windows
+            .states
+            .entry(key.to_string())
+            .and_modify(|state| {
+                if state.expires_at <= now {
+                    state.hits = 0;
+                    state.expires_at = now + window;
+                }
+            })
+            .or_insert(WindowState {
+                hits: 0,
+                expires_at: now + window,
+            });
+        state.hits += 1;
+
+        Ok(*state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hits_are_counted_per_key() {
+        let store = InMemoryCounterStore::default();
+        let window = Duration::from_secs(60);
+
+        store.hit("a", window).await.unwrap();
+        let a = store.hit("a", window).await.unwrap();
+        let b = store.hit("b", window).await.unwrap();
+
+        assert_eq!(a.hits, 2);
+        assert_eq!(b.hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_windows_start_over() {
+        let store = InMemoryCounterStore::default();
+
+        store.hit("a", Duration::ZERO).await.unwrap();
+        let state = store.hit("a", Duration::from_secs(60)).await.unwrap();
+
+        assert_eq!(state.hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_new_keys_are_limited_while_the_store_is_full() {
+        let store = InMemoryCounterStore::default();
+        let window = Duration::from_secs(60);
+        for i in 0..MAX_TRACKED_KEYS {
+            store.hit(&i.to_string(), Duration::ZERO).await.unwrap();
+        }
+
+        let tracked = store.hit("0", window).await.unwrap();
+        let new = store.hit("new", window).await.unwrap();
+        assert_eq!(tracked.hits, 1);
+        assert_eq!(new.hits, u32::MAX);
+
+        // The next sweep drops the expired windows and makes room again
+        store.windows.lock().unwrap().next_sweep = Utc::now();
+        let new = store.hit("new", window).await.unwrap();
+        assert_eq!(new.hits, 1);
+    }
+}
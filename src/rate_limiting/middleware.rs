@@ -0,0 +1,110 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::RETRY_AFTER,
+    web, Error, HttpResponse,
+};
+
+use super::{client_ip, retry_after_seconds, RateLimitKeys, RateLimiter};
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+// Only the target address of the form matters, the handler validates the rest
+#[derive(serde::Deserialize)]
+struct EmailField {
+    email: Option<String>,
+}
+
+// Throttles the wrapped routes per client ip, target email and target domain, answering with
+// 429 Too Many Requests and a Retry-After header once a limit is exceeded
+pub struct RateLimiting {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimiting {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitingMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitingMiddleware<S> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            // The body is read here to find the target email, the handler gets it back afterwards
+            let body = request.extract::<web::Bytes>().await?;
+            let email = serde_urlencoded::from_bytes::<EmailField>(&body)
+                .ok()
+                .and_then(|field| field.email);
+            let (_, mut payload) = actix_http::h1::Payload::create(true);
+            payload.unread_data(body);
+            request.set_payload(payload.into());
+
+            let ip = client_ip(request.request(), limiter.behind_proxy());
+
+            match limiter.check(&RateLimitKeys { ip, email }).await {
+                Ok(Some(retry_after)) => {
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((RETRY_AFTER, retry_after_seconds(retry_after).to_string()))
+                        .body("Too many requests, please try again later.");
+                    return Ok(request.into_response(response).map_into_right_body());
+                }
+                Ok(None) => {}
+                // Failing open, an unavailable counter store must not take subscriptions down
+                Err(error) => tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to check the rate limits"
+                ),
+            }
+
+            service
+                .call(request)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
@@ -0,0 +1,250 @@
+mod in_memory;
+mod middleware;
+mod postgres;
+
+pub use in_memory::InMemoryCounterStore;
+pub use middleware::RateLimiting;
+pub use postgres::PgCounterStore;
+
+use std::{
+    future::{ready, Ready},
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::{dev::Payload, http::header::X_FORWARDED_FOR, web, FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::configuration::{RateLimit, RateLimitSettings, RateLimitStoreKind};
+
+// Hits counted so far in the current window of a key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowState {
+    pub hits: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+// Fixed window counters, a window starts with the first hit of a key and lasts `window`
+#[async_trait::async_trait]
+pub trait CounterStore: Send + Sync {
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowState, anyhow::Error>;
+}
+
+// What a request is throttled by, so that nobody can make us send unlimited confirmation emails
+pub struct RateLimitKeys {
+    pub ip: Option<String>,
+    pub email: Option<String>,
+}
+
+pub struct RateLimiter {
+    store: Box<dyn CounterStore>,
+    settings: RateLimitSettings,
+}
+
+impl RateLimiter {
+    pub fn new(store: impl CounterStore + 'static, settings: RateLimitSettings) -> Self {
+        Self {
+            store: Box::new(store),
+            settings,
+        }
+    }
+
+    pub fn from_settings(settings: RateLimitSettings, db_pool: PgPool) -> Arc<Self> {
+        let limiter = match settings.store {
+            RateLimitStoreKind::InMemory => Self::new(InMemoryCounterStore::default(), settings),
+            RateLimitStoreKind::Postgres => Self::new(PgCounterStore::new(db_pool), settings),
+        };
+        Arc::new(limiter)
+    }
+
+    pub fn behind_proxy(&self) -> bool {
+        self.settings.behind_proxy
+    }
+
+    // Counts the request against every limit that applies to it. Returns how long the client
+    // has to wait when any of them is exceeded
+    pub async fn check(&self, keys: &RateLimitKeys) -> Result<Option<Duration>, anyhow::Error> {
+        let mut limits: Vec<(String, RateLimit)> = Vec::new();
+        if let Some(ip) = &keys.ip {
+            limits.push((format!("ip:{}", ip), self.settings.per_ip));
+        }
+        if let Some(email) = &keys.email {
+            let email = email.trim().to_lowercase();
+            if let Some((_, domain)) = email.rsplit_once('@') {
+                limits.push((format!("domain:{}", domain), self.settings.per_domain));
+            }
+            limits.push((format!("email:{}", email), self.settings.per_email));
+        }
+
+        let mut retry_after: Option<Duration> = None;
+        for (key, limit) in limits {
+            let state = self.store.hit(&key, limit.window()).await?;
+            if state.hits > limit.max_requests {
+                tracing::warn!(
+                    rate_limit.key = %key,
+                    rate_limit.hits = state.hits,
+                    rate_limit.max_requests = limit.max_requests,
+                    "A client exceeded a rate limit"
+                );
+                let wait = (state.expires_at - Utc::now())
+                    .to_std()
+                    .unwrap_or(Duration::ZERO);
+                retry_after = retry_after.max(Some(wait));
+            }
+        }
+
+        Ok(retry_after)
+    }
+}
+
+// Forwarded headers are only trusted behind a proxy, anyone could send them otherwise. Even then
+// only the rightmost X-Forwarded-For hop was added by the proxy, the client chose the others
+pub fn client_ip(request: &HttpRequest, behind_proxy: bool) -> Option<String> {
+    let peer_ip = || request.peer_addr().map(|address| address.ip().to_string());
+    if !behind_proxy {
+        return peer_ip();
+    }
+
+    request
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .last()
+        .map(str::to_string)
+        .or_else(peer_ip)
+}
+
+// Rounded up, so clients retrying right on time find the wait over
+pub fn retry_after_seconds(wait: Duration) -> u64 {
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+// The address of the client, as trusted by the rate limiter settings
+pub struct ClientIp(pub Option<String>);
+
+impl ClientIp {
+    pub fn for_request(request: &HttpRequest) -> Self {
+        let behind_proxy = request
+            .app_data::<web::Data<RateLimiter>>()
+            .is_some_and(|limiter| limiter.behind_proxy());
+        ClientIp(client_ip(request, behind_proxy))
+    }
+}
+
+impl FromRequest for ClientIp {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(ClientIp::for_request(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn limit(max_requests: u32) -> RateLimit {
+        RateLimit {
+            max_requests,
+            window_seconds: 60,
+        }
+    }
+
+    fn limiter(per_ip: u32, per_email: u32, per_domain: u32) -> RateLimiter {
+        RateLimiter::new(
+            InMemoryCounterStore::default(),
+            RateLimitSettings {
+                store: RateLimitStoreKind::InMemory,
+                behind_proxy: false,
+                per_ip: limit(per_ip),
+                per_email: limit(per_email),
+                per_domain: limit(per_domain),
+            },
+        )
+    }
+
+    fn keys(ip: &str, email: &str) -> RateLimitKeys {
+        RateLimitKeys {
+            ip: Some(ip.into()),
+            email: Some(email.into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_within_the_limits_pass() {
+        let limiter = limiter(2, 2, 2);
+
+        for _ in 0..2 {
+            let outcome = limiter.check(&keys("10.0.0.1", "a@example.com")).await;
+            assert_eq!(outcome.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_limit_is_enforced_on_its_own() {
+        let limiter = limiter(1, 1, 4);
+
+        // Same address, different emails and ips
+        limiter
+            .check(&keys("10.0.0.1", "a@example.com"))
+            .await
+            .unwrap();
+        let outcome = limiter.check(&keys("10.0.0.2", "A@Example.com ")).await;
+        assert!(outcome.unwrap().is_some());
+
+        // Same ip, different emails
+        let outcome = limiter.check(&keys("10.0.0.1", "b@example.com")).await;
+        assert!(outcome.unwrap().is_some());
+
+        // Same domain, different emails and ips
+        let outcome = limiter.check(&keys("10.0.0.3", "c@example.com")).await;
+        assert!(outcome.unwrap().is_none());
+        let outcome = limiter.check(&keys("10.0.0.4", "d@example.com")).await;
+        assert!(outcome.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_within_the_window() {
+        let limiter = limiter(1, 10, 10);
+        limiter
+            .check(&keys("10.0.0.1", "a@example.com"))
+            .await
+            .unwrap();
+
+        let wait = limiter
+            .check(&keys("10.0.0.1", "b@example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(wait > Duration::from_secs(58) && wait <= Duration::from_secs(60));
+    }
+
+    fn proxied_request(forwarded_for: &str) -> HttpRequest {
+        TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header((X_FORWARDED_FOR, forwarded_for))
+            .to_http_request()
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_hops_are_ignored_behind_a_proxy() {
+        // The client sent "198.51.100.66", the proxy appended the address it saw
+        let request = proxied_request("198.51.100.66, 203.0.113.9");
+
+        assert_eq!(client_ip(&request, true).as_deref(), Some("203.0.113.9"));
+    }
+
+    #[test]
+    fn test_forwarded_headers_are_ignored_without_a_proxy() {
+        let request = proxied_request("198.51.100.66");
+
+        assert_eq!(client_ip(&request, false).as_deref(), Some("10.0.0.1"));
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use rand::Rng;
+use sqlx::PgPool;
+
+use super::{CounterStore, WindowState};
+
+// Share of hits that also clean up expired counters, so the table does not grow unbounded
+const CLEANUP_PROBABILITY: f64 = 0.01;
+
+// Counters shared by every instance of the application
+pub struct PgCounterStore {
+    db_pool: PgPool,
+}
+
+impl PgCounterStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl CounterStore for PgCounterStore {
+    async fn hit(&self, key: &str, window: Duration) -> Result<WindowState, anyhow::Error> {
+        if rand::thread_rng().gen_bool(CLEANUP_PROBABILITY) {
+            sqlx::query!("DELETE FROM rate_limit_counters WHERE expires_at <= now()")
+                .execute(&self.db_pool)
+                .await
+                .context("Failed to delete the expired rate limit counters.")?;
+        }
+
+        // The upsert takes a row lock, concurrent hits of the same key are counted one by one
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_counters (key, hits, expires_at)
+            VALUES ($1, 1, now() + make_interval(secs => $2))
+            ON CONFLICT (key) DO UPDATE SET
+                hits = CASE
+                    WHEN rate_limit_counters.expires_at <= now() THEN 1
+                    ELSE rate_limit_counters.hits + 1
+                END,
+                expires_at = CASE
+                    WHEN rate_limit_counters.expires_at <= now() THEN EXCLUDED.expires_at
+                    ELSE rate_limit_counters.expires_at
+                END
+            RETURNING hits, expires_at
+            "#,
+            key,
+            window.as_secs_f64()
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .context("Failed to count a hit against the rate limit.")?;
+
+        Ok(WindowState {
+            hits: row.hits.try_into().unwrap_or(u32::MAX),
+            expires_at: row.expires_at,
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_tokens::{generate_api_token, hash_api_token, ApiTokenScope},
+    routes::{
+        admin::{require_login, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+};
+
+#[derive(serde::Deserialize)]
+pub struct NewApiTokenData {
+    name: String,
+    scopes: Option<Vec<String>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiTokenSummary {
+    api_token_id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+// The token itself is only ever returned here, only its hash is stored
+#[derive(serde::Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    summary: ApiTokenSummary,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiTokenError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no api token with the provided id.")]
+    UnknownToken,
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiTokenError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiTokenError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiTokenError::UnknownToken => StatusCode::NOT_FOUND,
+            ApiTokenError::AdminError(e) => e.status_code(),
+            ApiTokenError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ApiTokenError::AdminError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+fn parse_token_name(name: String) -> Result<String, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.len() > 100 {
+        return Err("The token name must be between 1 and 100 characters long.".into());
+    }
+
+    Ok(name)
+}
+
+// Tokens only allow publishing unless asked otherwise
+fn parse_scopes(scopes: Option<Vec<String>>) -> Result<Vec<String>, String> {
+    let Some(scopes) = scopes else {
+        return Ok(vec![ApiTokenScope::Publish.as_str().to_string()]);
+    };
+    if scopes.is_empty() {
+        return Err("An api token needs at least one scope.".into());
+    }
+    let mut parsed: Vec<String> = Vec::new();
+    for scope in scopes {
+        let scope = ApiTokenScope::parse(&scope)?.as_str().to_string();
+        if !parsed.contains(&scope) {
+            parsed.push(scope);
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[tracing::instrument(
+    name = "Create an api token",
+    skip(body, session, db_pool),
+    fields(user_id=tracing::field::Empty, api_token_id=tracing::field::Empty)
+)]
+pub async fn create_api_token(
+    body: web::Json<NewApiTokenData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiTokenError> {
+    let user_id = require_login(&session)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let body = body.0;
+    let name = parse_token_name(body.name).map_err(ApiTokenError::ValidationError)?;
+    let scopes = parse_scopes(body.scopes).map_err(ApiTokenError::ValidationError)?;
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(ApiTokenError::ValidationError(
+            "The expiry date of an api token must be in the future.".into(),
+        ));
+    }
+
+    let token = generate_api_token();
+    let summary = sqlx::query_as!(
+        ApiTokenSummary,
+        r#"
+        INSERT INTO api_tokens
+            (api_token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now(), $6)
+        RETURNING api_token_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(&token),
+        &scopes,
+        body.expires_at
+    )
+    .fetch_one(db_pool.get_ref())
+    .await
+    .context("Failed to store the new api token.")?;
+    tracing::Span::current().record(
+        "api_token_id",
+        tracing::field::display(&summary.api_token_id),
+    );
+
+    Ok(HttpResponse::Created().json(CreatedApiToken {
+        summary,
+        token: token.expose_secret().to_string(),
+    }))
+}
+
+#[tracing::instrument(name = "List api tokens", skip(session, db_pool))]
+pub async fn list_api_tokens(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiTokenError> {
+    let user_id = require_login(&session)?;
+
+    let tokens = sqlx::query_as!(
+        ApiTokenSummary,
+        r#"
+        SELECT api_token_id, name, scopes, created_at, expires_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the api tokens.")?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+// Revoked tokens are kept, so that their last use can still be looked up
+#[tracing::instrument(name = "Revoke an api token", skip(session, db_pool))]
+pub async fn revoke_api_token(
+    api_token_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiTokenError> {
+    let user_id = require_login(&session)?;
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = COALESCE(revoked_at, now())
+        WHERE api_token_id = $1 AND user_id = $2
+        "#,
+        api_token_id.into_inner(),
+        user_id
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to revoke the api token.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(ApiTokenError::UnknownToken);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,30 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::get_username,
+    routes::admin::{require_login, AdminError},
+    session_state::TypedSession,
+    types::templates::AdminDashboardTemplate,
+};
+
+#[tracing::instrument(name = "Show the admin dashboard", skip(session, db_pool))]
+pub async fn admin_dashboard(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AdminError> {
+    let user_id = require_login(&session)?;
+    let username = get_username(&db_pool, user_id).await?;
+
+    let html_body = AdminDashboardTemplate {
+        username: &username,
+    }
+    .render()
+    .context("Failed to render the admin dashboard.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
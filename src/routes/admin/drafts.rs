@@ -0,0 +1,459 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use similar::TextDiff;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authorization::SendNewsletters,
+    routes::{
+        admin::{require_login, AdminError},
+        authorize, error_chain_fmt, get_idempotency_key, publish_issue, EmailData, PublishError,
+    },
+    session_state::TypedSession,
+};
+
+#[derive(serde::Serialize)]
+pub struct DraftSummary {
+    draft_id: Uuid,
+    title: String,
+    revision: i32,
+    saved_at: DateTime<Utc>,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DraftRevision {
+    draft_id: Uuid,
+    revision: i32,
+    saved_by: String,
+    saved_at: DateTime<Utc>,
+    published_at: Option<DateTime<Utc>>,
+    content: serde_json::Value,
+}
+
+#[derive(serde::Serialize)]
+pub struct RevisionSummary {
+    revision: i32,
+    saved_by: String,
+    saved_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DiffParameters {
+    from: i32,
+    to: i32,
+}
+
+#[derive(thiserror::Error)]
+pub enum DraftError {
+    #[error("There is no draft with the provided id.")]
+    UnknownDraft,
+    #[error("The draft has no revision {0}.")]
+    UnknownRevision(i32),
+    #[error("The draft has already been published.")]
+    AlreadyPublished,
+    #[error(transparent)]
+    PublishError(#[from] PublishError),
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DraftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DraftError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DraftError::UnknownDraft | DraftError::UnknownRevision(_) => StatusCode::NOT_FOUND,
+            DraftError::AlreadyPublished => StatusCode::CONFLICT,
+            DraftError::PublishError(e) => e.status_code(),
+            DraftError::AdminError(e) => e.status_code(),
+            DraftError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            DraftError::PublishError(e) => e.error_response(),
+            DraftError::AdminError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Create a draft", skip(body, session, db_pool))]
+pub async fn create_draft(
+    body: web::Json<EmailData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    let user_id = require_login(&session)?;
+    let draft_id = Uuid::new_v4();
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO drafts (draft_id, created_by, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        draft_id,
+        user_id
+    );
+    db_transaction
+        .execute(query)
+        .await
+        .context("Failed to store the new draft.")?;
+    let summary = insert_revision(&mut db_transaction, draft_id, 1, user_id, &body).await?;
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a draft.")?;
+
+    Ok(HttpResponse::Created().json(summary))
+}
+
+#[tracing::instrument(name = "List drafts", skip(session, db_pool))]
+pub async fn list_drafts(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT
+            d.draft_id,
+            r.content->>'title' AS "title!",
+            r.revision,
+            r.saved_at,
+            d.published_at
+        FROM drafts d
+        JOIN draft_revisions r ON r.draft_id = d.draft_id
+        WHERE r.revision = (
+            SELECT MAX(revision) FROM draft_revisions WHERE draft_id = d.draft_id
+        )
+        ORDER BY r.saved_at DESC
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the drafts.")?;
+
+    Ok(HttpResponse::Ok().json(drafts))
+}
+
+#[tracing::instrument(name = "Show a draft", skip(session, db_pool))]
+pub async fn get_draft(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+
+    let revision = get_revision(&db_pool, *draft_id, None)
+        .await?
+        .ok_or(DraftError::UnknownDraft)?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+// Saving never overwrites, every update becomes the latest revision of the draft
+#[tracing::instrument(name = "Update a draft", skip(body, session, db_pool))]
+pub async fn update_draft(
+    draft_id: web::Path<Uuid>,
+    body: web::Json<EmailData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    let user_id = require_login(&session)?;
+    let draft_id = draft_id.into_inner();
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Locking the draft serializes concurrent saves, so they get consecutive revisions
+    let draft = sqlx::query!(
+        r#"
+        SELECT
+            d.published_at,
+            (SELECT MAX(revision) FROM draft_revisions WHERE draft_id = d.draft_id) AS "revision!"
+        FROM drafts d
+        WHERE d.draft_id = $1
+        FOR UPDATE
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut *db_transaction)
+    .await
+    .context("Failed to fetch the draft.")?
+    .ok_or(DraftError::UnknownDraft)?;
+    if draft.published_at.is_some() {
+        return Err(DraftError::AlreadyPublished);
+    }
+
+    let summary = insert_revision(
+        &mut db_transaction,
+        draft_id,
+        draft.revision + 1,
+        user_id,
+        &body,
+    )
+    .await?;
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update a draft.")?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(session, db_pool))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+    let draft_id = draft_id.into_inner();
+
+    // Published drafts are the record of what was sent, they are kept
+    let n_deleted_rows = sqlx::query!(
+        "DELETE FROM drafts WHERE draft_id = $1 AND published_at IS NULL",
+        draft_id
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to delete the draft.")?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        return match get_revision(&db_pool, draft_id, None).await? {
+            Some(_) => Err(DraftError::AlreadyPublished),
+            None => Err(DraftError::UnknownDraft),
+        };
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "List the revisions of a draft", skip(session, db_pool))]
+pub async fn list_draft_revisions(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+
+    let revisions = sqlx::query_as!(
+        RevisionSummary,
+        r#"
+        SELECT r.revision, u.username AS saved_by, r.saved_at
+        FROM draft_revisions r
+        JOIN users u ON u.user_id = r.saved_by
+        WHERE r.draft_id = $1
+        ORDER BY r.revision
+        "#,
+        *draft_id
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the revisions of the draft.")?;
+    if revisions.is_empty() {
+        return Err(DraftError::UnknownDraft);
+    }
+
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+#[tracing::instrument(name = "Show a revision of a draft", skip(session, db_pool))]
+pub async fn get_draft_revision(
+    path: web::Path<(Uuid, i32)>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+    let (draft_id, revision) = path.into_inner();
+
+    let revision = get_existing_revision(&db_pool, draft_id, revision).await?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+#[tracing::instrument(
+    name = "Diff two revisions of a draft",
+    skip(parameters, session, db_pool),
+    fields(from = parameters.from, to = parameters.to)
+)]
+pub async fn diff_draft_revisions(
+    draft_id: web::Path<Uuid>,
+    parameters: web::Query<DiffParameters>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    require_login(&session)?;
+
+    let from = get_existing_revision(&db_pool, *draft_id, parameters.from).await?;
+    let to = get_existing_revision(&db_pool, *draft_id, parameters.to).await?;
+    let from_document = diffable_document(&from.content);
+    let to_document = diffable_document(&to.content);
+    let diff = TextDiff::from_lines(&from_document, &to_document)
+        .unified_diff()
+        .header(
+            &format!("revision {}", from.revision),
+            &format!("revision {}", to.revision),
+        )
+        .to_string();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(diff))
+}
+
+// The latest revision goes through the same validation and sending logic as issues posted to
+// /newsletters
+#[tracing::instrument(name = "Publish a draft", skip(request, session, db_pool))]
+pub async fn publish_draft(
+    request: HttpRequest,
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, DraftError> {
+    let user_id = require_login(&session)?;
+    authorize::<SendNewsletters>(&db_pool, user_id).await?;
+    let draft_id = draft_id.into_inner();
+    let idempotency_key = get_idempotency_key(request.headers())?;
+
+    let revision = get_revision(&db_pool, draft_id, None)
+        .await?
+        .ok_or(DraftError::UnknownDraft)?;
+    let issue = serde_json::from_value::<EmailData>(revision.content).map_err(|e| {
+        DraftError::UnexpectedError(
+            anyhow::Error::new(e).context("The stored draft content is not a valid issue."),
+        )
+    })?;
+
+    // The draft is only marked as published along with the issue, a failed attempt leaves it
+    // editable and a retry with the same idempotency key gets the saved response
+    Ok(publish_issue(&db_pool, user_id, idempotency_key, &issue, Some(draft_id)).await?)
+}
+
+#[tracing::instrument(skip(db_transaction, content))]
+async fn insert_revision(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    revision: i32,
+    user_id: Uuid,
+    content: &EmailData,
+) -> Result<DraftSummary, anyhow::Error> {
+    let saved_at = Utc::now();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO draft_revisions (draft_id, revision, content, saved_by, saved_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        draft_id,
+        revision,
+        serde_json::to_value(content).context("Failed to serialize the draft content.")?,
+        user_id,
+        saved_at
+    );
+    db_transaction
+        .execute(query)
+        .await
+        .context("Failed to store the draft revision.")?;
+
+    Ok(DraftSummary {
+        draft_id,
+        title: content.title.clone(),
+        revision,
+        saved_at,
+        published_at: None,
+    })
+}
+
+// Fetches the given revision of a draft, or the latest one
+#[tracing::instrument(skip(db_pool))]
+async fn get_revision(
+    db_pool: &PgPool,
+    draft_id: Uuid,
+    revision: Option<i32>,
+) -> Result<Option<DraftRevision>, anyhow::Error> {
+    let revision = sqlx::query_as!(
+        DraftRevision,
+        r#"
+        SELECT
+            r.draft_id,
+            r.revision,
+            u.username AS saved_by,
+            r.saved_at,
+            d.published_at,
+            r.content
+        FROM draft_revisions r
+        JOIN drafts d ON d.draft_id = r.draft_id
+        JOIN users u ON u.user_id = r.saved_by
+        WHERE r.draft_id = $1 AND ($2::INT IS NULL OR r.revision = $2)
+        ORDER BY r.revision DESC
+        LIMIT 1
+        "#,
+        draft_id,
+        revision
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch the draft revision.")?;
+
+    Ok(revision)
+}
+
+async fn get_existing_revision(
+    db_pool: &PgPool,
+    draft_id: Uuid,
+    revision: i32,
+) -> Result<DraftRevision, DraftError> {
+    if let Some(revision) = get_revision(db_pool, draft_id, Some(revision)).await? {
+        return Ok(revision);
+    }
+    match get_revision(db_pool, draft_id, None).await? {
+        Some(_) => Err(DraftError::UnknownRevision(revision)),
+        None => Err(DraftError::UnknownDraft),
+    }
+}
+
+// Line-oriented rendering of a revision, so diffs point at the lines of the bodies that changed
+fn diffable_document(content: &serde_json::Value) -> String {
+    let mut document = String::new();
+    for (label, field) in [
+        ("Title", "/title"),
+        ("List", "/list"),
+        ("Segment", "/segment"),
+        ("Send at", "/send_at"),
+    ] {
+        if let Some(value) = content.pointer(field).and_then(|value| value.as_str()) {
+            document.push_str(&format!("{}: {}\n", label, value));
+        }
+    }
+    for (label, field) in [
+        ("Markdown", "/content/markdown"),
+        ("Html", "/content/html"),
+        ("Text", "/content/text"),
+    ] {
+        if let Some(value) = content.pointer(field).and_then(|value| value.as_str()) {
+            document.push_str(&format!("\n{}:\n{}\n", label, value));
+        }
+    }
+    document
+}
@@ -0,0 +1,135 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    routes::{
+        admin::{require_audience_manager, require_login, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+    types::ListSlug,
+};
+
+#[derive(serde::Deserialize)]
+pub struct NewListData {
+    slug: String,
+    name: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListSummary {
+    list_id: Uuid,
+    slug: String,
+    name: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ListManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The list identifier is already taken.")]
+    SlugTaken,
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ListManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ListManagementError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ListManagementError::SlugTaken => StatusCode::CONFLICT,
+            ListManagementError::AdminError(e) => e.status_code(),
+            ListManagementError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ListManagementError::AdminError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+fn parse_list_name(name: String) -> Result<String, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.len() > 100 {
+        return Err("The list name must be between 1 and 100 characters long.".into());
+    }
+
+    Ok(name)
+}
+
+#[tracing::instrument(
+    name = "Create a mailing list",
+    skip(body, session, db_pool),
+    fields(slug = %body.slug)
+)]
+pub async fn create_list(
+    body: web::Json<NewListData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListManagementError> {
+    require_audience_manager(&session, &db_pool).await?;
+
+    let body = body.0;
+    let slug = ListSlug::parse(body.slug).map_err(ListManagementError::ValidationError)?;
+    let name = parse_list_name(body.name).map_err(ListManagementError::ValidationError)?;
+
+    let list_id = Uuid::new_v4();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        list_id,
+        slug.as_ref(),
+        name
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to insert the new mailing list.")?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        return Err(ListManagementError::SlugTaken);
+    }
+
+    Ok(HttpResponse::Created().json(ListSummary {
+        list_id,
+        slug: slug.as_ref().to_string(),
+        name,
+    }))
+}
+
+#[tracing::instrument(name = "List mailing lists", skip(session, db_pool))]
+pub async fn list_lists(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ListManagementError> {
+    require_login(&session)?;
+
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT list_id, slug, name
+        FROM lists
+        ORDER BY slug
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the mailing lists.")?;
+
+    Ok(HttpResponse::Ok().json(lists))
+}
@@ -0,0 +1,16 @@
+use actix_web::{http::header::LOCATION, HttpResponse};
+
+use crate::{
+    routes::admin::{require_login, AdminError},
+    session_state::TypedSession,
+};
+
+#[tracing::instrument(name = "Log out an admin user", skip(session))]
+pub async fn log_out(session: TypedSession) -> Result<HttpResponse, AdminError> {
+    require_login(&session)?;
+    session.log_out();
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/login"))
+        .finish())
+}
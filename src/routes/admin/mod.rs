@@ -0,0 +1,97 @@
+mod api_tokens;
+mod dashboard;
+mod drafts;
+mod lists;
+mod logout;
+mod password;
+mod subscribers;
+mod two_factor;
+mod users;
+
+use actix_web::{
+    http::{header::LOCATION, StatusCode},
+    HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub use api_tokens::*;
+pub use dashboard::*;
+pub use drafts::*;
+pub use lists::*;
+pub use logout::*;
+pub use password::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
+
+use crate::{
+    authorization::{get_role, Role},
+    routes::error_chain_fmt,
+    session_state::TypedSession,
+};
+
+#[derive(thiserror::Error)]
+pub enum AdminError {
+    #[error("The user has not logged in.")]
+    Anonymous,
+    #[error("Your role is not allowed to do this.")]
+    Forbidden,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AdminError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AdminError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AdminError::Anonymous => HttpResponse::SeeOther()
+                .insert_header((LOCATION, "/login"))
+                .finish(),
+            AdminError::Forbidden => {
+                HttpResponse::build(StatusCode::FORBIDDEN).body(self.to_string())
+            }
+            AdminError::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+// Every admin page requires a logged-in user, anonymous visitors are sent to the login form
+fn require_login(session: &TypedSession) -> Result<Uuid, AdminError> {
+    session
+        .get_user_id()
+        .context("Failed to read the user id from the session.")?
+        .ok_or(AdminError::Anonymous)
+}
+
+// Pages that change the audience or expose personal data are closed to some roles
+async fn require_role(
+    session: &TypedSession,
+    db_pool: &PgPool,
+    allowed_roles: &[Role],
+) -> Result<Uuid, AdminError> {
+    let user_id = require_login(session)?;
+    match get_role(db_pool, user_id).await? {
+        Some(role) if allowed_roles.contains(&role) => Ok(user_id),
+        _ => Err(AdminError::Forbidden),
+    }
+}
+
+// Managing users and the personal data of subscribers is reserved to admins
+async fn require_admin(session: &TypedSession, db_pool: &PgPool) -> Result<Uuid, AdminError> {
+    require_role(session, db_pool, &[Role::Admin]).await
+}
+
+// Lists and tags decide who receives an issue, editors only write drafts
+async fn require_audience_manager(
+    session: &TypedSession,
+    db_pool: &PgPool,
+) -> Result<Uuid, AdminError> {
+    require_role(session, db_pool, &[Role::Admin, Role::Publisher]).await
+}
@@ -0,0 +1,162 @@
+use actix_web::{
+    http::{
+        header::{ContentType, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use askama_actix::Template;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{
+        self, get_username, validate_credentials, AuthError, Credentials, SecondFactor,
+    },
+    rate_limiting::{retry_after_seconds, ClientIp},
+    routes::{
+        admin::{require_login, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+    types::{templates::ChangePasswordTemplate, NewPassword},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordFormData {
+    current_password: SecretString,
+    new_password: SecretString,
+    new_password_check: SecretString,
+}
+
+#[derive(thiserror::Error)]
+pub enum ChangePasswordError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The current password is incorrect.")]
+    InvalidCurrentPassword(#[source] anyhow::Error),
+    #[error("Too many failed attempts, please try again later.")]
+    TooManyAttempts { retry_after_seconds: u64 },
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ChangePasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ChangePasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ChangePasswordError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ChangePasswordError::InvalidCurrentPassword(_) => StatusCode::UNAUTHORIZED,
+            ChangePasswordError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ChangePasswordError::AdminError(e) => e.status_code(),
+            ChangePasswordError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ChangePasswordError::AdminError(e) => e.error_response(),
+            ChangePasswordError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            ChangePasswordError::ValidationError(_)
+            | ChangePasswordError::InvalidCurrentPassword(_)
+            | ChangePasswordError::TooManyAttempts { .. } => {
+                let message = self.to_string();
+                match render_change_password_form(self.status_code(), Some(&message)) {
+                    Ok(mut response) => {
+                        if let ChangePasswordError::TooManyAttempts {
+                            retry_after_seconds,
+                        } = self
+                        {
+                            response
+                                .headers_mut()
+                                .insert(RETRY_AFTER, HeaderValue::from(*retry_after_seconds));
+                        }
+                        response
+                    }
+                    Err(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+                }
+            }
+        }
+    }
+}
+
+fn render_change_password_form(
+    status_code: StatusCode,
+    message: Option<&str>,
+) -> Result<HttpResponse, anyhow::Error> {
+    let html_body = ChangePasswordTemplate { message }
+        .render()
+        .context("Failed to render the change password form.")?;
+
+    Ok(HttpResponse::build(status_code)
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+pub async fn change_password_form(session: TypedSession) -> Result<HttpResponse, AdminError> {
+    require_login(&session)?;
+
+    Ok(render_change_password_form(StatusCode::OK, None)?)
+}
+
+#[tracing::instrument(
+    name = "Change the password of an admin user",
+    skip(form, session, db_pool, client_ip),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn change_password(
+    form: web::Form<ChangePasswordFormData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, ChangePasswordError> {
+    let user_id = require_login(&session)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return Err(ChangePasswordError::ValidationError(
+            "You entered two different new passwords - the field values must match.".into(),
+        ));
+    }
+    let new_password =
+        NewPassword::parse(form.0.new_password).map_err(ChangePasswordError::ValidationError)?;
+
+    let credentials = Credentials {
+        username: get_username(&db_pool, user_id).await?,
+        password: form.0.current_password,
+    };
+    // The user went through the second factor when they logged in
+    validate_credentials(
+        credentials,
+        SecondFactor::AlreadyVerified,
+        client_ip.0.as_deref(),
+        &db_pool,
+    )
+    .await
+    .map_err(|e| match e {
+        AuthError::InvalidCredentials(_) => ChangePasswordError::InvalidCurrentPassword(e.into()),
+        AuthError::LockedOut { retry_after } => ChangePasswordError::TooManyAttempts {
+            retry_after_seconds: retry_after_seconds(retry_after),
+        },
+        AuthError::SecondFactorRequired | AuthError::UnexpectedError(_) => {
+            ChangePasswordError::UnexpectedError(e.into())
+        }
+    })?;
+
+    authentication::change_password(user_id, new_password, &db_pool).await?;
+
+    Ok(render_change_password_form(
+        StatusCode::OK,
+        Some("Your password has been changed."),
+    )?)
+}
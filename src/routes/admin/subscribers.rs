@@ -0,0 +1,249 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    consent::{get_consent_log, ConsentRecord},
+    personal_data::{erase_personal_data, export_personal_data},
+    routes::{
+        admin::{require_admin, require_audience_manager, require_login, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+    startup::TombstoneSecret,
+    types::{SubscriberEmail, SubscriberTag},
+};
+
+#[derive(serde::Deserialize)]
+pub struct TagData {
+    tag: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberSummary {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    tags: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberEmailParameters {
+    email: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentExport {
+    subscriber_id: Uuid,
+    email: String,
+    events: Vec<ConsentRecord>,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber with the provided id.")]
+    UnknownSubscriber,
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberManagementError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscriberManagementError::UnknownSubscriber => StatusCode::NOT_FOUND,
+            SubscriberManagementError::AdminError(e) => e.status_code(),
+            SubscriberManagementError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscriberManagementError::AdminError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "List subscribers", skip(session, db_pool))]
+pub async fn list_subscribers(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    require_login(&session)?;
+
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT
+            s.id AS subscriber_id,
+            s.email,
+            s.name,
+            COALESCE(
+                array_agg(t.tag ORDER BY t.tag) FILTER (WHERE t.tag IS NOT NULL),
+                '{}'
+            ) AS "tags!"
+        FROM subscriptions s
+        LEFT JOIN subscriber_tags t ON t.subscriber_id = s.id
+        GROUP BY s.id
+        ORDER BY s.email
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the subscribers.")?;
+
+    Ok(HttpResponse::Ok().json(subscribers))
+}
+
+// Proof of consent to hand over when a regulator or the subscriber asks for it
+#[tracing::instrument(
+    name = "Export the consent log of a subscriber",
+    skip(session, db_pool)
+)]
+pub async fn export_subscriber_consent(
+    subscriber_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    require_admin(&session, &db_pool).await?;
+    let subscriber_id = subscriber_id.into_inner();
+
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .context("Failed to look up the subscriber.")?
+    .ok_or(SubscriberManagementError::UnknownSubscriber)?;
+    let events = get_consent_log(db_pool.get_ref(), subscriber_id)
+        .await
+        .context("Failed to fetch the consent log of the subscriber.")?;
+
+    Ok(HttpResponse::Ok().json(ConsentExport {
+        subscriber_id,
+        email: subscriber.email,
+        events,
+    }))
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(body, session, db_pool), fields(tag = %body.tag))]
+pub async fn tag_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    require_audience_manager(&session, &db_pool).await?;
+    let tag =
+        SubscriberTag::parse(body.0.tag).map_err(SubscriberManagementError::ValidationError)?;
+    let subscriber_id = subscriber_id.into_inner();
+
+    // Tagging twice is a no-op, so retried requests are harmless
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT id, $2
+        FROM subscriptions
+        WHERE id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag.as_ref()
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to tag the subscriber.")?
+    .rows_affected();
+    if n_inserted_rows == 0 && !subscriber_exists(&db_pool, subscriber_id).await? {
+        return Err(SubscriberManagementError::UnknownSubscriber);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(session, db_pool))]
+pub async fn untag_subscriber(
+    path: web::Path<(Uuid, String)>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    require_audience_manager(&session, &db_pool).await?;
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = SubscriberTag::parse(tag).map_err(SubscriberManagementError::ValidationError)?;
+
+    if !subscriber_exists(&db_pool, subscriber_id).await? {
+        return Err(SubscriberManagementError::UnknownSubscriber);
+    }
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag.as_ref()
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to untag the subscriber.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn subscriber_exists(db_pool: &PgPool, subscriber_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!("SELECT id FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(db_pool)
+        .await
+        .context("Failed to look up the subscriber.")?;
+
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Export the personal data of a subscriber", skip_all)]
+pub async fn export_subscriber_data(
+    query_params: web::Query<SubscriberEmailParameters>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    require_admin(&session, &db_pool).await?;
+    let email = SubscriberEmail::parse(query_params.0.email)
+        .map_err(SubscriberManagementError::ValidationError)?;
+
+    let export = export_personal_data(&db_pool, email.as_ref())
+        .await?
+        .ok_or(SubscriberManagementError::UnknownSubscriber)?;
+
+    Ok(HttpResponse::Ok().json(export))
+}
+
+#[tracing::instrument(
+    name = "Erase the personal data of a subscriber",
+    skip_all,
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn erase_subscriber_data(
+    query_params: web::Query<SubscriberEmailParameters>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    tombstone_secret: web::Data<TombstoneSecret>,
+) -> Result<HttpResponse, SubscriberManagementError> {
+    let user_id = require_admin(&session, &db_pool).await?;
+    tracing::Span::current().record("user_id", tracing::field::display(user_id));
+    let email = SubscriberEmail::parse(query_params.0.email)
+        .map_err(SubscriberManagementError::ValidationError)?;
+
+    if !erase_personal_data(&db_pool, email.as_ref(), &tombstone_secret.0).await? {
+        return Err(SubscriberManagementError::UnknownSubscriber);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
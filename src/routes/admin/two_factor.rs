@@ -0,0 +1,203 @@
+use actix_web::{
+    http::{header::RETRY_AFTER, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use chrono::Utc;
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::get_username,
+    login_attempts::{begin_attempt, LockoutCheck, LoginAttempt},
+    rate_limiting::{retry_after_seconds, ClientIp},
+    routes::{
+        admin::{require_login, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+    two_factor::{self, TwoFactorError},
+};
+
+// Shown by authenticator apps next to the account name
+const ISSUER: &str = "zero2prod";
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorCodeData {
+    code: SecretString,
+}
+
+#[derive(serde::Serialize)]
+pub struct TwoFactorStatus {
+    enrolled: bool,
+    unused_recovery_codes: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct TwoFactorEnrolment {
+    secret: String,
+    provisioning_uri: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum TwoFactorSetupError {
+    #[error("Two-factor authentication is already enabled.")]
+    AlreadyEnrolled,
+    #[error("Two-factor authentication is not enabled.")]
+    NotEnrolled,
+    #[error("There is no two-factor enrolment waiting for confirmation.")]
+    NoPendingEnrolment,
+    #[error("The two-factor code is invalid.")]
+    InvalidCode,
+    #[error("Too many failed attempts, please try again later.")]
+    TooManyAttempts { retry_after_seconds: u64 },
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TwoFactorSetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TwoFactorSetupError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TwoFactorSetupError::AlreadyEnrolled | TwoFactorSetupError::NoPendingEnrolment => {
+                StatusCode::CONFLICT
+            }
+            TwoFactorSetupError::NotEnrolled => StatusCode::NOT_FOUND,
+            TwoFactorSetupError::InvalidCode => StatusCode::BAD_REQUEST,
+            TwoFactorSetupError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            TwoFactorSetupError::AdminError(e) => e.status_code(),
+            TwoFactorSetupError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TwoFactorSetupError::AdminError(e) => e.error_response(),
+            TwoFactorSetupError::TooManyAttempts {
+                retry_after_seconds,
+            } => HttpResponse::build(self.status_code())
+                .insert_header((RETRY_AFTER, retry_after_seconds.to_string()))
+                .body(self.to_string()),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get two-factor status", skip(session, db_pool))]
+pub async fn get_two_factor_status(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorSetupError> {
+    let user_id = require_login(&session)?;
+
+    Ok(HttpResponse::Ok().json(TwoFactorStatus {
+        enrolled: two_factor::is_enrolled(db_pool.get_ref(), user_id).await?,
+        unused_recovery_codes: two_factor::unused_recovery_codes(&db_pool, user_id).await?,
+    }))
+}
+
+// The secret is shown until the enrolment is confirmed, starting over replaces it
+#[tracing::instrument(name = "Start two-factor enrolment", skip(session, db_pool))]
+pub async fn start_two_factor_enrolment(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorSetupError> {
+    let user_id = require_login(&session)?;
+    let username = get_username(&db_pool, user_id).await?;
+
+    let enrolment = two_factor::start_enrolment(&db_pool, user_id, ISSUER, &username)
+        .await?
+        .ok_or(TwoFactorSetupError::AlreadyEnrolled)?;
+
+    Ok(HttpResponse::Created().json(TwoFactorEnrolment {
+        secret: enrolment.secret,
+        provisioning_uri: enrolment.provisioning_uri,
+    }))
+}
+
+#[tracing::instrument(name = "Confirm two-factor enrolment", skip(body, session, db_pool))]
+pub async fn confirm_two_factor_enrolment(
+    body: web::Json<TwoFactorCodeData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, TwoFactorSetupError> {
+    let user_id = require_login(&session)?;
+
+    let recovery_codes = two_factor::confirm_enrolment(&db_pool, user_id, &body.code, Utc::now())
+        .await
+        .map_err(|e| match e {
+            TwoFactorError::Required => TwoFactorSetupError::NoPendingEnrolment,
+            TwoFactorError::InvalidCode => TwoFactorSetupError::InvalidCode,
+            TwoFactorError::UnexpectedError(e) => TwoFactorSetupError::UnexpectedError(e),
+        })?;
+    tracing::info!(
+        security.event = "two_factor_enabled",
+        security.user_id = %user_id,
+        "Enabled two-factor authentication"
+    );
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+// A valid code is asked for, so that a hijacked session cannot turn the second factor off.
+// Wrong codes count against the login lockout, otherwise the session could guess them freely
+#[tracing::instrument(name = "Disable two-factor", skip(body, session, db_pool, client_ip))]
+pub async fn disable_two_factor(
+    body: web::Json<TwoFactorCodeData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, TwoFactorSetupError> {
+    let user_id = require_login(&session)?;
+    if !two_factor::is_enrolled(db_pool.get_ref(), user_id).await? {
+        return Err(TwoFactorSetupError::NotEnrolled);
+    }
+
+    let username = get_username(&db_pool, user_id).await?;
+    let attempt = LoginAttempt {
+        username: &username,
+        client_ip: client_ip.0.as_deref(),
+    };
+    let pending_attempt = match begin_attempt(&db_pool, &attempt).await? {
+        LockoutCheck::Allowed(pending_attempt) => pending_attempt,
+        LockoutCheck::LockedOut(retry_after) => {
+            return Err(TwoFactorSetupError::TooManyAttempts {
+                retry_after_seconds: retry_after_seconds(retry_after),
+            })
+        }
+    };
+    match two_factor::verify_second_factor(&db_pool, user_id, Some(&body.code), Utc::now()).await {
+        Ok(()) => {}
+        Err(TwoFactorError::Required | TwoFactorError::InvalidCode) => {
+            tracing::warn!(
+                security.event = "two_factor_failed",
+                security.username = %username,
+                security.client_ip = ?attempt.client_ip,
+                "Failed two-factor verification"
+            );
+            pending_attempt.fail();
+            return Err(TwoFactorSetupError::InvalidCode);
+        }
+        Err(TwoFactorError::UnexpectedError(e)) => return Err(e.into()),
+    }
+    pending_attempt.succeed(&db_pool).await?;
+    two_factor::disable(&db_pool, user_id).await?;
+    tracing::warn!(
+        security.event = "two_factor_disabled",
+        security.user_id = %user_id,
+        "Disabled two-factor authentication"
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
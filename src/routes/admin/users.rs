@@ -0,0 +1,289 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::compute_password_hash,
+    authorization::Role,
+    login_attempts,
+    routes::{
+        admin::{require_admin, AdminError},
+        error_chain_fmt,
+    },
+    session_state::TypedSession,
+    session_store::delete_user_sessions,
+    telemetry::spawn_blocking_thread_with_tracing,
+    types::{NewPassword, SubscriberEmail},
+};
+
+#[derive(serde::Deserialize)]
+pub struct NewUserData {
+    username: String,
+    password: SecretString,
+    email: Option<String>,
+    role: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleData {
+    role: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserSummary {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    disabled: bool,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UserManagementError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The username is already taken.")]
+    UsernameTaken,
+    #[error("There is no user with the provided id.")]
+    UnknownUser,
+    #[error(transparent)]
+    AdminError(#[from] AdminError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UserManagementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UserManagementError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UserManagementError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            UserManagementError::UsernameTaken => StatusCode::CONFLICT,
+            UserManagementError::UnknownUser => StatusCode::NOT_FOUND,
+            UserManagementError::AdminError(e) => e.status_code(),
+            UserManagementError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            UserManagementError::AdminError(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
+}
+
+fn parse_username(username: String) -> Result<String, String> {
+    let username = username.trim().to_string();
+    if username.is_empty() || username.len() > 64 {
+        return Err("The username must be between 1 and 64 characters long.".into());
+    }
+
+    Ok(username)
+}
+
+#[tracing::instrument(
+    name = "Create a publishing user",
+    skip(body, session, db_pool),
+    fields(username = %body.username)
+)]
+pub async fn create_user(
+    body: web::Json<NewUserData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    require_admin(&session, &db_pool).await?;
+
+    let body = body.0;
+    let username = parse_username(body.username).map_err(UserManagementError::ValidationError)?;
+    // New users can send newsletters, but not manage other users
+    let role = body
+        .role
+        .as_deref()
+        .map(Role::parse)
+        .transpose()
+        .map_err(UserManagementError::ValidationError)?
+        .unwrap_or(Role::Publisher);
+    let password =
+        NewPassword::parse(body.password).map_err(UserManagementError::ValidationError)?;
+    let email = body
+        .email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(UserManagementError::ValidationError)?;
+    let password_hash = spawn_blocking_thread_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task")??;
+
+    let user_id = insert_user(&db_pool, &username, email.as_ref(), role, password_hash)
+        .await?
+        .ok_or(UserManagementError::UsernameTaken)?;
+
+    Ok(HttpResponse::Created().json(UserSummary {
+        user_id,
+        username,
+        email: email.map(|email| email.as_ref().to_string()),
+        role: role.as_str().to_string(),
+        disabled: false,
+        locked_until: None,
+    }))
+}
+
+#[tracing::instrument(name = "List publishing users", skip(session, db_pool))]
+pub async fn list_users(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    require_admin(&session, &db_pool).await?;
+
+    let users = sqlx::query_as!(
+        UserSummary,
+        r#"
+        SELECT u.user_id, u.username, u.email, u.role, u.disabled, a.locked_until AS "locked_until?"
+        FROM users u
+        LEFT JOIN failed_login_attempts a
+            ON a.scope = 'username' AND a.subject = u.username AND a.locked_until > now()
+        ORDER BY u.username
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to fetch the users.")?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[tracing::instrument(name = "Disable a publishing user", skip(session, db_pool))]
+pub async fn disable_user(
+    user_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    let current_user_id = require_admin(&session, &db_pool).await?;
+    let user_id = user_id.into_inner();
+    if current_user_id == user_id {
+        return Err(UserManagementError::ValidationError(
+            "You cannot disable your own account.".into(),
+        ));
+    }
+
+    let n_updated_rows = sqlx::query!(
+        "UPDATE users SET disabled = true WHERE user_id = $1",
+        user_id
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to disable the user.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(UserManagementError::UnknownUser);
+    }
+
+    // Disabled users must not keep acting through a session opened before
+    delete_user_sessions(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+    name = "Change the role of a publishing user",
+    skip(body, session, db_pool)
+)]
+pub async fn change_user_role(
+    user_id: web::Path<Uuid>,
+    body: web::Json<RoleData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    let current_user_id = require_admin(&session, &db_pool).await?;
+    let user_id = user_id.into_inner();
+    // Otherwise the last admin could lock everybody out of user management
+    if current_user_id == user_id {
+        return Err(UserManagementError::ValidationError(
+            "You cannot change your own role.".into(),
+        ));
+    }
+    let role = Role::parse(&body.role).map_err(UserManagementError::ValidationError)?;
+
+    let n_updated_rows = sqlx::query!(
+        "UPDATE users SET role = $2 WHERE user_id = $1",
+        user_id,
+        role.as_str()
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to change the role of the user.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(UserManagementError::UnknownUser);
+    }
+    tracing::info!(
+        security.event = "role_changed",
+        security.user_id = %user_id,
+        security.role = role.as_str(),
+        security.changed_by = %current_user_id,
+        "Changed the role of a user"
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Lets a locked out user log in again right away, their failed attempts are forgotten
+#[tracing::instrument(name = "Unlock a publishing user", skip(session, db_pool))]
+pub async fn unlock_user(
+    user_id: web::Path<Uuid>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UserManagementError> {
+    let current_user_id = require_admin(&session, &db_pool).await?;
+    let user_id = user_id.into_inner();
+
+    if !login_attempts::unlock_user(&db_pool, user_id).await? {
+        return Err(UserManagementError::UnknownUser);
+    }
+    tracing::info!(
+        security.event = "account_unlocked",
+        security.user_id = %user_id,
+        security.unlocked_by = %current_user_id,
+        "Unlocked a user account"
+    );
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Save new user in the database", skip(db_pool, password_hash))]
+async fn insert_user(
+    db_pool: &PgPool,
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    role: Role,
+    password_hash: SecretString,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let user_id = Uuid::new_v4();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, role, password_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        email.map(|email| email.as_ref()),
+        role.as_str(),
+        password_hash.expose_secret()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to insert the new user.")?
+    .rows_affected();
+
+    Ok((n_inserted_rows > 0).then_some(user_id))
+}
@@ -1,9 +1,14 @@
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::{routes::error_chain_fmt, types::SubscriptionToken};
+use crate::{
+    consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentEventType},
+    routes::error_chain_fmt,
+    types::SubscriptionToken,
+};
 
 #[derive(serde::Deserialize)]
 pub struct QueryParameters {
@@ -16,6 +21,10 @@ pub enum ConfirmError {
     ValidationError(String),
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The confirmation link has expired, please request a new one.")]
+    ExpiredToken,
+    #[error("This subscription can no longer be confirmed.")]
+    InactiveMembership,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -25,6 +34,8 @@ impl ResponseError for ConfirmError {
         match self {
             ConfirmError::ValidationError(_) => StatusCode::BAD_REQUEST,
             ConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::ExpiredToken => StatusCode::GONE,
+            ConfirmError::InactiveMembership => StatusCode::CONFLICT,
             ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -36,61 +47,111 @@ impl std::fmt::Debug for ConfirmError {
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(query_params, db_pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(request, query_params, db_pool)
+)]
 pub async fn confirm(
+    request: HttpRequest,
     query_params: web::Query<QueryParameters>,
     db_pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, ConfirmError> {
     let subscription_token = SubscriptionToken::parse(query_params.subscription_token.clone())
         .map_err(ConfirmError::ValidationError)?;
 
-    let id = get_subscriber_id(&db_pool, subscription_token)
+    let (id, list_id, expires_at) = get_subscriber_id(&db_pool, &subscription_token)
         .await
         .context("Failed to get the token's associated subscriber id.")?
         .ok_or(ConfirmError::UnknownToken)?;
 
-    if subscriber_already_confirmed(&db_pool, id)
+    let status = get_membership_status(&db_pool, id, list_id)
         .await
-        .context("Failed to fetch the status of the token's associated subscriber")?
-    {
-        return Ok(HttpResponse::Ok().body("Already confirmed."));
+        .context("Failed to fetch the status of the token's associated subscriber")?;
+    match status.as_str() {
+        "confirmed" => return Ok(HttpResponse::Ok().body("Already confirmed.")),
+        "pending_confirmation" => {}
+        // Bounced, complained and unsubscribed memberships stay as they are
+        _ => return Err(ConfirmError::InactiveMembership),
+    }
+
+    if expires_at <= Utc::now() {
+        return Err(ConfirmError::ExpiredToken);
     }
 
-    confirm_subscriber(&db_pool, id)
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    if !confirm_subscriber(&mut db_transaction, id, list_id)
+        .await
+        .context("Failed to update subscriber's status.")?
+    {
+        return Err(ConfirmError::InactiveMembership);
+    }
+    let consent_event = ConsentEvent {
+        subscriber_id: id,
+        list_id,
+        event_type: ConsentEventType::Confirm,
+        token: Some(subscription_token.as_ref()),
+        source: "confirmation_link",
+    };
+    record_consent_event(
+        &mut *db_transaction,
+        &consent_event,
+        &ConsentContext::from_request(&request),
+    )
+    .await
+    .context("Failed to record the consent of a confirmed subscriber.")?;
+    db_transaction
+        .commit()
         .await
-        .context("Failed to update subscriber's status.")?;
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
 #[tracing::instrument(
     name = "Check the status of the subscriber associated to the token",
-    skip(db_pool, subscriber_id)
+    skip(db_pool, subscriber_id, list_id)
 )]
-async fn subscriber_already_confirmed(
+async fn get_membership_status(
     db_pool: &PgPool,
     subscriber_id: Uuid,
-) -> Result<bool, sqlx::Error> {
+    list_id: Uuid,
+) -> Result<String, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT status FROM subscriptions WHERE id = $1"#,
-        subscriber_id
+        r#"SELECT status FROM list_memberships WHERE subscriber_id = $1 AND list_id = $2"#,
+        subscriber_id,
+        list_id
     )
     .fetch_one(db_pool)
     .await?;
 
-    Ok(result.status == "confirmed")
+    Ok(result.status)
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(db_pool, subscriber_id))]
-async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
-    )
-    .execute(db_pool)
-    .await?;
-
-    Ok(())
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(db_transaction, subscriber_id, list_id)
+)]
+// Returns false when the membership stopped waiting for a confirmation in the meantime
+async fn confirm_subscriber(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_id
+    );
+    let result = db_transaction.execute(query).await?;
+
+    Ok(result.rows_affected() == 1)
 }
 
 #[tracing::instrument(
@@ -99,14 +160,18 @@ async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(),
 )]
 async fn get_subscriber_id(
     db_pool: &PgPool,
-    subscription_token: SubscriptionToken,
-) -> Result<Option<Uuid>, sqlx::Error> {
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<(Uuid, Uuid, DateTime<Utc>)>, sqlx::Error> {
     let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
+        r#"
+        SELECT subscriber_id, list_id, expires_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        "#,
         subscription_token.as_ref()
     )
     .fetch_optional(db_pool)
     .await?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| (r.subscriber_id, r.list_id, r.expires_at)))
 }
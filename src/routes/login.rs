@@ -0,0 +1,126 @@
+use actix_web::{
+    http::{
+        header::{ContentType, LOCATION, RETRY_AFTER},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use askama_actix::Template;
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{validate_credentials, AuthError, Credentials, SecondFactor},
+    rate_limiting::{retry_after_seconds, ClientIp},
+    routes::error_chain_fmt,
+    session_state::TypedSession,
+    types::templates::LoginTemplate,
+};
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: SecretString,
+    two_factor_code: Option<SecretString>,
+}
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Please enter the code from your authenticator app.")]
+    SecondFactorRequired,
+    #[error("Too many failed login attempts, please try again later.")]
+    TooManyAttempts { retry_after_seconds: u64 },
+    #[error("Something went wrong")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LoginError::AuthError(_) | LoginError::SecondFactorRequired => StatusCode::UNAUTHORIZED,
+            LoginError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            LoginError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // Send the user back to the login form, letting them know what went wrong
+    fn error_response(&self) -> HttpResponse {
+        let error_message = self.to_string();
+        match render_login_form(Some(&error_message)) {
+            Ok(html_body) => {
+                let mut response = HttpResponse::build(self.status_code());
+                if let LoginError::TooManyAttempts {
+                    retry_after_seconds,
+                } = self
+                {
+                    response.insert_header((RETRY_AFTER, retry_after_seconds.to_string()));
+                }
+                response.content_type(ContentType::html()).body(html_body)
+            }
+            Err(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+fn render_login_form(error_message: Option<&str>) -> Result<String, askama::Error> {
+    LoginTemplate { error_message }.render()
+}
+
+pub async fn login_form() -> Result<HttpResponse, LoginError> {
+    let html_body = render_login_form(None).context("Failed to render the login form.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(
+    name = "Log in an admin user",
+    skip(form, db_pool, session, client_ip),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, LoginError> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let second_factor = SecondFactor::Code(form.0.two_factor_code);
+    let user_id =
+        validate_credentials(credentials, second_factor, client_ip.0.as_deref(), &db_pool)
+            .await
+            .map_err(|e| match e {
+                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::SecondFactorRequired => LoginError::SecondFactorRequired,
+                AuthError::LockedOut { retry_after } => LoginError::TooManyAttempts {
+                    retry_after_seconds: retry_after_seconds(retry_after),
+                },
+                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+            })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    // Rotate the session key on login to prevent session fixation attacks
+    session.renew();
+    session
+        .insert_user_id(user_id)
+        .context("Failed to store the user id in the session.")?;
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/dashboard"))
+        .finish())
+}
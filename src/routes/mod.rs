@@ -1,9 +1,29 @@
+mod admin;
 mod confirm_subscriptions;
 mod health_check;
+mod login;
+mod newsletter_issues;
+mod newsletter_previews;
 mod newsletters;
+mod personal_data;
+mod resend_confirmation;
+mod scheduled_newsletters;
 mod subscriptions;
+mod tracking;
+mod unsubscribe;
+mod webhooks;
 
+pub use admin::*;
 pub use confirm_subscriptions::*;
 pub use health_check::*;
+pub use login::*;
+pub use newsletter_issues::*;
+pub use newsletter_previews::*;
 pub use newsletters::*;
+pub use personal_data::*;
+pub use resend_confirmation::*;
+pub use scheduled_newsletters::*;
 pub use subscriptions::*;
+pub use tracking::*;
+pub use unsubscribe::*;
+pub use webhooks::*;
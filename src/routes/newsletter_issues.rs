@@ -0,0 +1,139 @@
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use askama_actix::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    routes::error_chain_fmt,
+    types::{
+        templates::{NewsletterIssueSummary, NewsletterIssueTemplate, NewsletterIssuesTemplate},
+        IssueTemplate, RecipientDetails,
+    },
+};
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+#[derive(thiserror::Error)]
+pub enum ArchiveError {
+    #[error("There is no newsletter issue with the provided id.")]
+    UnknownIssue,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for ArchiveError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ArchiveError::UnknownIssue => StatusCode::NOT_FOUND,
+            ArchiveError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+struct NewsletterIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(db_pool))]
+pub async fn list_newsletter_issues(
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let issues = get_newsletter_issues(&db_pool)
+        .await
+        .context("Failed to fetch the published newsletter issues.")?;
+
+    let html_body = NewsletterIssuesTemplate { issues }
+        .render()
+        .context("Failed to render the newsletter archive page.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(name = "Show a newsletter issue", skip(db_pool))]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ArchiveError> {
+    let issue = get_newsletter_issue_by_id(&db_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue.")?
+        .ok_or(ArchiveError::UnknownIssue)?;
+
+    let published_at = issue.published_at.format(DATE_FORMAT).to_string();
+    let html_body = NewsletterIssueTemplate {
+        title: &anonymize(&issue.title, IssueTemplate::render_text),
+        published_at: &published_at,
+        html_content: &anonymize(&issue.html_content, IssueTemplate::render_html),
+    }
+    .render()
+    .context("Failed to render the newsletter issue page.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+// The archive is public, placeholders are left blank instead of showing anybody's details.
+// Issues published before placeholders existed are shown as they are
+fn anonymize(source: &str, render: fn(&IssueTemplate, &RecipientDetails) -> String) -> String {
+    match IssueTemplate::parse(source) {
+        Ok(template) => render(&template, &RecipientDetails::anonymous()),
+        Err(_) => source.to_string(),
+    }
+}
+
+// Segmented issues were only meant for part of a list, they stay out of the public archive
+#[tracing::instrument(name = "Get published newsletter issues", skip(db_pool))]
+async fn get_newsletter_issues(
+    db_pool: &PgPool,
+) -> Result<Vec<NewsletterIssueSummary>, sqlx::Error> {
+    let issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status = 'published' AND segment IS NULL
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(|r| NewsletterIssueSummary {
+        newsletter_issue_id: r.newsletter_issue_id.to_string(),
+        title: anonymize(&r.title, IssueTemplate::render_text),
+        published_at: r.published_at.format(DATE_FORMAT).to_string(),
+    })
+    .collect();
+
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get newsletter issue by id", skip(db_pool))]
+async fn get_newsletter_issue_by_id(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'published' AND segment IS NULL
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+}
@@ -0,0 +1,134 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::{ReadNewsletters, SendNewsletters},
+    email_client::EmailClient,
+    issue_delivery_worker::{personalize_issue, PersonalizedIssue},
+    routes::{validate_issue_templates, Authorized, EmailData, PublishError},
+    startup::{ApplicationBaseUrl, TestRecipients},
+    types::{RecipientDetails, SubscriberEmail},
+};
+
+// Stand-in recipient for previews
+const SAMPLE_NAME: &str = "Jane Doe";
+const SAMPLE_EMAIL: &str = "jane.doe@example.com";
+
+#[derive(serde::Deserialize)]
+pub struct TestSendData {
+    #[serde(flatten)]
+    issue: EmailData,
+    // Defaults to the address of the publisher
+    recipient: Option<String>,
+}
+
+struct Publisher {
+    username: String,
+    email: Option<String>,
+}
+
+#[tracing::instrument(name = "Preview a newsletter issue", skip(_reader, base_url, body))]
+pub async fn preview_newsletter(
+    _reader: Authorized<ReadNewsletters>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    body: web::Json<EmailData>,
+) -> Result<HttpResponse, PublishError> {
+    let unsubscribe_url = sample_unsubscribe_url(&base_url.0);
+    let recipient = RecipientDetails {
+        name: SAMPLE_NAME,
+        email: SAMPLE_EMAIL,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let issue = render_issue(&body, &recipient)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(issue.html_content))
+}
+
+#[tracing::instrument(
+    name = "Send a test newsletter issue",
+    skip(publisher, db_pool, email_client, base_url, test_recipients, body),
+    fields(user_id = %publisher.user_id, recipient = tracing::field::Empty)
+)]
+pub async fn test_send_newsletter(
+    publisher: Authorized<SendNewsletters>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    test_recipients: web::Data<TestRecipients>,
+    body: web::Json<TestSendData>,
+) -> Result<HttpResponse, PublishError> {
+    let publisher = get_publisher(&db_pool, publisher.user_id)
+        .await
+        .context("Failed to fetch the details of the publisher.")?;
+
+    let recipient = match (&body.recipient, &publisher.email) {
+        (Some(recipient), _) => recipient,
+        (None, Some(email)) => email,
+        (None, None) => {
+            return Err(PublishError::ValidationError(
+                "There is no email address to send the test issue to".into(),
+            ))
+        }
+    };
+    tracing::Span::current().record("recipient", tracing::field::display(recipient));
+    // Test sends must not become a way to mail arbitrary people
+    let is_allowed = |address: &String| address.eq_ignore_ascii_case(recipient);
+    if !publisher.email.iter().any(is_allowed) && !test_recipients.0.iter().any(is_allowed) {
+        return Err(PublishError::ValidationError(format!(
+            "{} is not allowed to receive test issues",
+            recipient
+        )));
+    }
+    let recipient_email =
+        SubscriberEmail::parse(recipient.clone()).map_err(PublishError::ValidationError)?;
+
+    let unsubscribe_url = sample_unsubscribe_url(&base_url.0);
+    let details = RecipientDetails {
+        name: &publisher.username,
+        email: recipient,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let issue = render_issue(&body.issue, &details)?;
+    email_client
+        .send_email(
+            &recipient_email,
+            &format!("[Test] {}", issue.title),
+            &issue.html_content,
+            &issue.text_content,
+        )
+        .await
+        .context("Failed to send the test issue.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Nobody is unsubscribed through previews, the link leads to the form without a token
+fn sample_unsubscribe_url(base_url: &str) -> String {
+    format!("{}/subscriptions/unsubscribe", base_url)
+}
+
+fn render_issue(
+    issue: &EmailData,
+    recipient: &RecipientDetails,
+) -> Result<PersonalizedIssue, PublishError> {
+    let content = issue.content.render(&issue.title)?;
+    validate_issue_templates(&issue.title, &content)?;
+
+    personalize_issue(&issue.title, &content.html, &content.text, recipient)
+        .map_err(PublishError::ValidationError)
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_publisher(db_pool: &PgPool, user_id: Uuid) -> Result<Publisher, sqlx::Error> {
+    sqlx::query_as!(
+        Publisher,
+        "SELECT username, email FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+}
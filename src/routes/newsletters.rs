@@ -1,41 +1,111 @@
+use std::{future::Future, marker::PhantomData, pin::Pin};
+
 use actix_web::{
+    dev::Payload,
     http::{
         header::{self, HeaderMap, HeaderValue},
         StatusCode,
     },
-    web, HttpRequest, HttpResponse, ResponseError,
+    web, FromRequest, HttpRequest, HttpResponse, ResponseError,
 };
 use anyhow::Context;
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use askama_actix::Template;
 use base64::Engine;
-use secrecy::{ExposeSecret, Secret, SecretString};
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use secrecy::{Secret, SecretString};
+use sqlx::{Executor, PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
 
 use crate::{
-    email_client::EmailClient, routes::error_chain_fmt,
-    telemetry::spawn_blocking_thread_with_tracing, types::SubscriberEmail,
+    api_tokens::{authenticate_api_token, ApiTokenScope},
+    authentication::{validate_credentials, AuthError, Credentials, SecondFactor},
+    authorization::{get_role, Permission, Role, SendNewsletters},
+    idempotency::{save_response, try_processing, NextAction},
+    mailing_lists::{get_list_id, parse_list_slug},
+    markdown,
+    rate_limiting::{retry_after_seconds, ClientIp},
+    routes::error_chain_fmt,
+    session_state::TypedSession,
+    tracking::store_issue_links,
+    types::{templates::NewsletterEmailTemplate, IdempotencyKey, IssueTemplate, Segment},
 };
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct EmailData {
-    title: String,
-    content: Content,
+    pub(crate) title: String,
+    pub(crate) content: Content,
+    // Issues with a send date are held back until the scheduler publishes them
+    pub(crate) send_at: Option<DateTime<Utc>>,
+    // Slug of the mailing list the issue goes out to
+    pub(crate) list: Option<String>,
+    // Tag expression restricting the issue to a segment of the list, e.g. `beta AND NOT eu`
+    pub(crate) segment: Option<String>,
 }
 
-#[derive(serde::Deserialize)]
+// Issues are authored either in markdown or as explicit bodies, which take precedence over the
+// ones rendered from markdown
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct Content {
-    html: String,
-    text: String,
+    pub(crate) markdown: Option<String>,
+    pub(crate) html: Option<String>,
+    pub(crate) text: Option<String>,
+}
+
+pub struct IssueContent {
+    pub(crate) html: String,
+    pub(crate) text: String,
+}
+
+impl Content {
+    pub(crate) fn render(&self, title: &str) -> Result<IssueContent, PublishError> {
+        let html = match (&self.html, &self.markdown) {
+            (Some(html), _) => html.clone(),
+            (None, Some(markdown)) => NewsletterEmailTemplate {
+                title,
+                html_content: &markdown::render_html(markdown),
+            }
+            .render()
+            .context("Failed to render the html body of the newsletter issue.")?,
+            (None, None) => return Err(missing_body_error("html")),
+        };
+        let text = match (&self.text, &self.markdown) {
+            (Some(text), _) => text.clone(),
+            (None, Some(markdown)) => markdown::render_text(markdown),
+            (None, None) => return Err(missing_body_error("text")),
+        };
+
+        Ok(IssueContent { html, text })
+    }
 }
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+fn missing_body_error(body: &str) -> PublishError {
+    PublishError::ValidationError(format!(
+        "The newsletter issue needs either a markdown source or an explicit {} body",
+        body
+    ))
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("The api token does not have the {} scope.", .0.as_str())]
+    MissingScope(ApiTokenScope),
+    #[error("The {} role is not allowed to {}.", .role.as_str(), .permission)]
+    InsufficientRole {
+        role: Role,
+        permission: &'static str,
+    },
+    #[error("Too many failed authentication attempts, please try again later.")]
+    TooManyAttempts { retry_after_seconds: u64 },
+    #[error("There is no newsletter issue with the provided id.")]
+    UnknownIssue,
+    #[error("The newsletter issue is no longer scheduled.")]
+    NotScheduled,
+    #[error("The draft has already been published.")]
+    DraftAlreadyPublished,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -43,6 +113,20 @@ pub enum PublishError {
 impl ResponseError for PublishError {
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
         match self {
+            PublishError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            PublishError::TooManyAttempts {
+                retry_after_seconds,
+            } => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after_seconds.to_string()))
+                .finish(),
+            PublishError::MissingScope(_) | PublishError::InsufficientRole { .. } => {
+                HttpResponse::build(StatusCode::FORBIDDEN).body(self.to_string())
+            }
+            PublishError::UnknownIssue => HttpResponse::new(StatusCode::NOT_FOUND),
+            PublishError::NotScheduled => HttpResponse::new(StatusCode::CONFLICT),
+            PublishError::DraftAlreadyPublished => {
+                HttpResponse::build(StatusCode::CONFLICT).body(self.to_string())
+            }
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
@@ -66,12 +150,34 @@ impl std::fmt::Debug for PublishError {
     }
 }
 
-struct Credentials {
-    username: String,
-    password: SecretString,
+// Users enrolled in two-factor authentication send their code along with Basic credentials
+fn two_factor_code(headers: &HeaderMap) -> Result<Option<SecretString>, PublishError> {
+    let Some(header_value) = headers.get("X-Two-Factor-Code") else {
+        return Ok(None);
+    };
+    let code = header_value
+        .to_str()
+        .context("The 'X-Two-Factor-Code' header was not a valid UTF-8 string")
+        .map_err(PublishError::AuthError)?;
+
+    Ok(Some(Secret::new(code.to_string())))
+}
+
+// None when the request authenticates some other way
+fn bearer_token(headers: &HeaderMap) -> Result<Option<SecretString>, anyhow::Error> {
+    let Some(header_value) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let header_value = header_value
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF-8 string")?;
+
+    Ok(header_value
+        .strip_prefix("Bearer ")
+        .map(|token| Secret::new(token.trim().to_string())))
 }
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+pub(crate) fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     let header_value = headers
         .get("Authorization")
         .context("The 'Authorization' header was missing")?
@@ -102,136 +208,419 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Erro
     })
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, db_pool))]
-async fn validate_credentials(
-    credentials: Credentials,
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(publisher, request, db_pool, body),
+    fields(
+        user_id = %publisher.user_id,
+        api_token_id = ?publisher.api_token_id
+    )
+)]
+pub async fn publish_newsletter(
+    publisher: Authorized<SendNewsletters>,
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    body: web::Json<EmailData>,
+) -> Result<HttpResponse, PublishError> {
+    let user_id = publisher.user_id;
+    let idempotency_key = get_idempotency_key(request.headers())?;
+
+    publish_issue(&db_pool, user_id, idempotency_key, &body, None).await
+}
+
+// Validates an issue, then either schedules it or queues its delivery. The draft the issue comes
+// from, if any, is marked as published in the same transaction
+#[tracing::instrument(skip(db_pool, idempotency_key, issue))]
+pub(crate) async fn publish_issue(
     db_pool: &PgPool,
-) -> Result<uuid::Uuid, PublishError> {
-    let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
-    if let Some((stored_user_id, stored_password_hash)) =
-        get_stored_credentials(db_pool, &credentials.username)
+    user_id: Uuid,
+    idempotency_key: Option<IdempotencyKey>,
+    issue: &EmailData,
+    draft_id: Option<Uuid>,
+) -> Result<HttpResponse, PublishError> {
+    if let Some(send_at) = issue.send_at {
+        validate_send_at(send_at)?;
+    }
+    let list_slug = parse_list_slug(issue.list.clone()).map_err(PublishError::ValidationError)?;
+    if let Some(segment) = &issue.segment {
+        Segment::parse(segment).map_err(PublishError::ValidationError)?;
+    }
+    let content = issue.content.render(&issue.title)?;
+    validate_issue_templates(&issue.title, &content)?;
+    let list_id = get_list_id(db_pool, &list_slug)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .ok_or_else(|| {
+            PublishError::ValidationError(format!(
+                "There is no mailing list called {}",
+                list_slug.as_ref()
+            ))
+        })?;
+
+    let mut db_transaction = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(db_pool, idempotency_key, user_id).await? {
+            NextAction::StartProcessing(db_transaction) => db_transaction,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => db_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?,
+    };
+    if let Some(draft_id) = draft_id {
+        if !claim_draft(&mut db_transaction, draft_id)
             .await
-            .map_err(PublishError::UnexpectedError)?
-    {
-        user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash;
+            .context("Failed to mark the draft as published.")?
+        {
+            return Err(PublishError::DraftAlreadyPublished);
+        }
     }
 
-    spawn_blocking_thread_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
-    })
-    .await
-    .context("Failed to spawn blocking task")
-    .map_err(PublishError::UnexpectedError)??;
+    let newsletter_issue_id =
+        insert_newsletter_issue(&mut db_transaction, user_id, list_id, issue, &content)
+            .await
+            .context("Failed to store newsletter issue details.")?;
+    store_issue_links(&mut db_transaction, newsletter_issue_id, &content.html)
+        .await
+        .context("Failed to store the links of the newsletter issue.")?;
+
+    let response = match issue.send_at {
+        Some(send_at) => HttpResponse::Accepted().json(ScheduledIssue {
+            newsletter_issue_id,
+            title: issue.title.clone(),
+            send_at,
+        }),
+        None => {
+            enqueue_delivery_tasks(&mut db_transaction, newsletter_issue_id)
+                .await
+                .context("Failed to enqueue delivery tasks.")?;
+            HttpResponse::Ok().finish()
+        }
+    };
+    let response = match &idempotency_key {
+        Some(idempotency_key) => {
+            save_response(db_transaction, idempotency_key, user_id, response).await?
+        }
+        None => {
+            db_transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to publish a newsletter issue.")?;
+            response
+        }
+    };
 
-    user_id.ok_or_else(|| PublishError::AuthError(anyhow::anyhow!("Unknown username")))
+    Ok(response)
 }
 
-fn verify_password_hash(
-    expected_password_hash: SecretString,
-    password_candidate: SecretString,
+#[derive(serde::Serialize)]
+pub struct ScheduledIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub send_at: DateTime<Utc>,
+}
+
+// Placeholders are filled in while sending, a broken template must not fail halfway through
+pub(crate) fn validate_issue_templates(
+    title: &str,
+    content: &IssueContent,
 ) -> Result<(), PublishError> {
-    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
-        .context("Failed to parse PHC string")
-        .map_err(PublishError::UnexpectedError)?;
-
-    Argon2::default()
-        .verify_password(
-            password_candidate.expose_secret().as_bytes(),
-            &expected_password_hash,
-        )
-        .context("Invalid password")
-        .map_err(PublishError::AuthError)
+    for (field, source) in [
+        ("title", title),
+        ("html body", &content.html),
+        ("text body", &content.text),
+    ] {
+        IssueTemplate::parse(source).map_err(|e| {
+            PublishError::ValidationError(format!(
+                "Invalid {} of the newsletter issue: {}",
+                field, e
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn validate_send_at(send_at: DateTime<Utc>) -> Result<(), PublishError> {
+    if send_at <= Utc::now() {
+        return Err(PublishError::ValidationError(
+            "The send date of a newsletter issue must be in the future".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+// A user allowed to perform P, whichever way they authenticated
+pub struct Authorized<P> {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub api_token_id: Option<Uuid>,
+    permission: PhantomData<P>,
+}
+
+impl<P: Permission + 'static> FromRequest for Authorized<P> {
+    type Error = PublishError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let request = request.clone();
+        Box::pin(async move {
+            let session = TypedSession::extract(&request)
+                .into_inner()
+                .map_err(|e| anyhow::anyhow!("Failed to read the session: {}", e))?;
+            let db_pool = request
+                .app_data::<web::Data<PgPool>>()
+                .context("The database pool is missing from the application data")?;
+
+            let (user_id, api_token_id) =
+                authenticate_publisher(&request, &session, db_pool, P::SCOPE).await?;
+            let role = authorize::<P>(db_pool, user_id).await?;
+
+            Ok(Authorized {
+                user_id,
+                role,
+                api_token_id,
+                permission: PhantomData,
+            })
+        })
+    }
 }
 
-#[tracing::instrument(name = "Get stored credentials", skip(db_pool, username))]
-async fn get_stored_credentials(
+// Checks that the role of an authenticated user grants P
+pub(crate) async fn authorize<P: Permission>(
     db_pool: &PgPool,
-    username: &str,
-) -> Result<Option<(uuid::Uuid, SecretString)>, anyhow::Error> {
-    let row = sqlx::query!(
-        r#"
-        SELECT user_id, password_hash
-        FROM users
-        WHERE username = $1
-        "#,
-        username
-    )
-    .fetch_optional(db_pool)
-    .await
-    .context("Failed to perform query to retrieve stored credentials")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    user_id: Uuid,
+) -> Result<Role, PublishError> {
+    let role = get_role(db_pool, user_id)
+        .await?
+        .ok_or_else(|| PublishError::AuthError(anyhow::anyhow!("Unknown or disabled user")))?;
+    if !P::is_granted_to(role) {
+        tracing::warn!(
+            security.event = "permission_denied",
+            security.user_id = %user_id,
+            security.role = role.as_str(),
+            security.permission = P::NAME,
+            "A user lacks the role for an action"
+        );
+        return Err(PublishError::InsufficientRole {
+            role,
+            permission: P::NAME,
+        });
+    }
 
-    Ok(row)
+    Ok(role)
 }
 
+// Explicit credentials take precedence, otherwise the publisher must have logged in through the
+// admin login form. Only api tokens are restricted to a scope, their id is returned as well
 #[tracing::instrument(
-    name = "Publish a newsletter issue",
-    skip(request, db_pool, body, email_client),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
-    )]
-pub async fn publish_newsletter(
-    request: HttpRequest,
-    db_pool: web::Data<PgPool>,
-    body: web::Json<EmailData>,
-    email_client: web::Data<EmailClient>,
-) -> Result<HttpResponse, PublishError> {
-    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-
-    let user_id = validate_credentials(credentials, &db_pool).await?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-
-    let confirmed_subscribers = get_confirmed_subscribers(&db_pool).await?;
-    for subscriber in confirmed_subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid",);
-            }
+    name = "Authenticate publisher",
+    skip(request, session, db_pool),
+    fields(username=tracing::field::Empty, api_token_id=tracing::field::Empty)
+)]
+async fn authenticate_publisher(
+    request: &HttpRequest,
+    session: &TypedSession,
+    db_pool: &PgPool,
+    scope: ApiTokenScope,
+) -> Result<(Uuid, Option<Uuid>), PublishError> {
+    if !request.headers().contains_key(header::AUTHORIZATION) {
+        if let Some(user_id) = session
+            .get_user_id()
+            .context("Failed to read the user id from the session")?
+        {
+            return Ok((user_id, None));
         }
     }
-    Ok(HttpResponse::Ok().finish())
+
+    if let Some(token) = bearer_token(request.headers()).map_err(PublishError::AuthError)? {
+        let identity = authenticate_api_token(db_pool, &token)
+            .await?
+            .ok_or_else(|| PublishError::AuthError(anyhow::anyhow!("Unknown api token")))?;
+        tracing::Span::current().record(
+            "api_token_id",
+            tracing::field::display(&identity.api_token_id),
+        );
+        if !identity.allows(scope) {
+            return Err(PublishError::MissingScope(scope));
+        }
+        return Ok((identity.user_id, Some(identity.api_token_id)));
+    }
+
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let client_ip = ClientIp::for_request(request);
+    let second_factor = SecondFactor::Code(two_factor_code(request.headers())?);
+    let user_id = validate_credentials(credentials, second_factor, client_ip.0.as_deref(), db_pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) | AuthError::SecondFactorRequired => {
+                PublishError::AuthError(e.into())
+            }
+            AuthError::LockedOut { retry_after } => PublishError::TooManyAttempts {
+                retry_after_seconds: retry_after_seconds(retry_after),
+            },
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        })?;
+
+    Ok((user_id, None))
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(db_pool))]
-async fn get_confirmed_subscribers(
-    db_pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
+// Retried requests carrying the same key are only processed once per user
+pub(crate) fn get_idempotency_key(
+    headers: &HeaderMap,
+) -> Result<Option<IdempotencyKey>, PublishError> {
+    let Some(header_value) = headers.get("Idempotency-Key") else {
+        return Ok(None);
+    };
+    let idempotency_key = header_value
+        .to_str()
+        .map_err(|_| {
+            PublishError::ValidationError(
+                "The 'Idempotency-Key' header was not a valid UTF-8 string".into(),
+            )
+        })?
+        .to_string();
+
+    IdempotencyKey::parse(idempotency_key)
+        .map(Some)
+        .map_err(PublishError::ValidationError)
+}
+
+#[tracing::instrument(name = "Save newsletter issue details", skip_all)]
+async fn insert_newsletter_issue(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    list_id: Uuid,
+    issue: &EmailData,
+    content: &IssueContent,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let (status, published_at) = match issue.send_at {
+        Some(_) => ("scheduled", None),
+        None => ("published", Some(Utc::now())),
+    };
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            published_by,
+            status,
+            send_at,
+            list_id,
+            segment
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+        newsletter_issue_id,
+        issue.title,
+        content.text,
+        content.html,
+        published_at,
+        user_id,
+        status,
+        issue.send_at,
+        list_id,
+        issue.segment
+    );
+    db_transaction.execute(query).await?;
+
+    Ok(newsletter_issue_id)
+}
+
+// Concurrent requests for the same draft wait on its row, only the first one gets to publish it
+#[tracing::instrument(skip(db_transaction))]
+async fn claim_draft(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
         r#"
-            SELECT email
-            FROM subscriptions
-            WHERE status = 'confirmed'
-        "#
+        UPDATE drafts
+        SET published_at = now()
+        WHERE draft_id = $1 AND published_at IS NULL
+        "#,
+        draft_id
+    )
+    .execute(&mut **db_transaction)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// Every confirmed member of the issue's list, restricted to its segment if it has one, gets its
+// own task. This way a failure delivering to one of them does not affect the rest of the list.
+// The actual sending is done by the issue delivery worker
+#[tracing::instrument(name = "Enqueue delivery tasks for confirmed subscribers", skip_all)]
+pub(crate) async fn enqueue_delivery_tasks(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let segment = sqlx::query!(
+        "SELECT segment FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
     )
-    .fetch_all(db_pool)
+    .fetch_one(&mut **db_transaction)
     .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
+    .segment
+    .map(|segment| Segment::parse(&segment))
+    .transpose()
+    .map_err(|e| anyhow::anyhow!("Invalid segment stored for the newsletter issue: {}", e))?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT i.newsletter_issue_id, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = m.list_id
+        WHERE m.status = 'confirmed' AND s.suppression IS NULL AND i.newsletter_issue_id =
+        "#,
+    );
+    query.push_bind(newsletter_issue_id);
+    if let Some(segment) = &segment {
+        query.push(" AND ");
+        push_segment_condition(&mut query, segment);
+    }
+    query.build().execute(&mut **db_transaction).await?;
 
-    Ok(confirmed_subscribers)
+    Ok(())
+}
+
+// Tags are always bound as parameters, only the shape of the expression ends up in the SQL text
+fn push_segment_condition(query: &mut QueryBuilder<'_, Postgres>, segment: &Segment) {
+    match segment {
+        Segment::Tag(tag) => {
+            query.push(
+                "EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ",
+            );
+            query.push_bind(tag.as_ref().to_string());
+            query.push(")");
+        }
+        Segment::Not(segment) => {
+            query.push("(NOT ");
+            push_segment_condition(query, segment);
+            query.push(")");
+        }
+        Segment::And(left, right) => {
+            query.push("(");
+            push_segment_condition(query, left);
+            query.push(" AND ");
+            push_segment_condition(query, right);
+            query.push(")");
+        }
+        Segment::Or(left, right) => {
+            query.push("(");
+            push_segment_condition(query, left);
+            query.push(" OR ");
+            push_segment_condition(query, right);
+            query.push(")");
+        }
+    }
 }
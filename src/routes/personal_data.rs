@@ -0,0 +1,94 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    personal_data::{erase_personal_data, export_personal_data},
+    routes::error_chain_fmt,
+    startup::TombstoneSecret,
+    types::UnsubscribeToken,
+};
+
+// Every issue carries the unsubscribe token, it proves the request comes from the subscriber
+#[derive(serde::Deserialize)]
+pub struct PersonalDataParameters {
+    unsubscribe_token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PersonalDataError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for PersonalDataError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PersonalDataError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PersonalDataError::UnknownToken => StatusCode::UNAUTHORIZED,
+            PersonalDataError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for PersonalDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[tracing::instrument(name = "Export own personal data", skip(query_params, db_pool))]
+pub async fn export_own_personal_data(
+    query_params: web::Query<PersonalDataParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PersonalDataError> {
+    let email = get_subscriber_email(&db_pool, query_params.0).await?;
+
+    let export = export_personal_data(&db_pool, &email)
+        .await?
+        .ok_or(PersonalDataError::UnknownToken)?;
+
+    Ok(HttpResponse::Ok().json(export))
+}
+
+#[tracing::instrument(
+    name = "Erase own personal data",
+    skip(query_params, db_pool, tombstone_secret)
+)]
+pub async fn erase_own_personal_data(
+    query_params: web::Query<PersonalDataParameters>,
+    db_pool: web::Data<PgPool>,
+    tombstone_secret: web::Data<TombstoneSecret>,
+) -> Result<HttpResponse, PersonalDataError> {
+    let email = get_subscriber_email(&db_pool, query_params.0).await?;
+
+    if !erase_personal_data(&db_pool, &email, &tombstone_secret.0).await? {
+        return Err(PersonalDataError::UnknownToken);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get subscriber email from unsubscribe_token", skip_all)]
+async fn get_subscriber_email(
+    db_pool: &PgPool,
+    query_params: PersonalDataParameters,
+) -> Result<String, PersonalDataError> {
+    let unsubscribe_token = UnsubscribeToken::parse(query_params.unsubscribe_token)
+        .map_err(PersonalDataError::ValidationError)?;
+
+    let row = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE unsubscribe_token = $1",
+        unsubscribe_token.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to get the token's associated subscriber.")?
+    .ok_or(PersonalDataError::UnknownToken)?;
+
+    Ok(row.email)
+}
@@ -0,0 +1,164 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::subscriptions::{generate_token, send_confirmation_email, store_subscriber_token};
+use crate::{
+    email_client::EmailClient,
+    mailing_lists::parse_list_slug,
+    routes::error_chain_fmt,
+    startup::ApplicationBaseUrl,
+    types::{ListSlug, SubscriberEmail},
+};
+
+// Minimum time between two confirmation emails sent to the same address
+const RESEND_COOLDOWN_SECONDS: i64 = 300;
+
+#[derive(serde::Deserialize)]
+pub struct ResendConfirmationFormData {
+    email: String,
+    list: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ResendConfirmationError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendConfirmationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendConfirmationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ResendConfirmationError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ResendConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, db_pool, email_client, application_base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendConfirmationFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ResendConfirmationError> {
+    let form = form.0;
+    let subscriber_email =
+        SubscriberEmail::parse(form.email).map_err(ResendConfirmationError::ValidationError)?;
+    let list_slug = parse_list_slug(form.list).map_err(ResendConfirmationError::ValidationError)?;
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Unknown and already confirmed addresses get the same response, so this endpoint does not
+    // leak who is subscribed
+    let Some((subscriber_id, list_id)) =
+        get_pending_membership(&mut db_transaction, &subscriber_email, &list_slug)
+            .await
+            .context("Failed to look up the pending subscriber.")?
+    else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    if let Some(last_sent_at) = get_last_token_creation(&mut db_transaction, subscriber_id)
+        .await
+        .context("Failed to fetch the last confirmation token of the subscriber.")?
+    {
+        // The cooldown gets the same answer too, only pending addresses can be in one
+        if (Utc::now() - last_sent_at).num_seconds() < RESEND_COOLDOWN_SECONDS {
+            tracing::info!("Skipped a confirmation email sent again too soon");
+            return Ok(HttpResponse::Ok().finish());
+        }
+    }
+
+    let subscription_token = generate_token();
+    store_subscriber_token(
+        &mut db_transaction,
+        subscriber_id,
+        list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the new confirmation token.")?;
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction for a new confirmation token.")?;
+
+    send_confirmation_email(
+        &email_client,
+        &subscriber_email,
+        &application_base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to send confirmation email.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Locks the subscriber row, so concurrent requests for the same address cannot bypass the cooldown
+#[tracing::instrument(name = "Get pending list membership", skip_all)]
+async fn get_pending_membership(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    subscriber_email: &SubscriberEmail,
+    list_slug: &ListSlug,
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.id, m.list_id
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE
+            s.email = $1 AND
+            l.slug = $2 AND
+            m.status = 'pending_confirmation' AND
+            s.suppression IS NULL
+        FOR UPDATE OF s
+        "#,
+        subscriber_email.as_ref(),
+        list_slug.as_ref()
+    )
+    .fetch_optional(&mut **db_transaction)
+    .await?;
+
+    Ok(row.map(|r| (r.id, r.list_id)))
+}
+
+#[tracing::instrument(
+    name = "Get last confirmation token creation time",
+    skip(db_transaction)
+)]
+async fn get_last_token_creation(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT MAX(created_at) AS last_created_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut **db_transaction)
+    .await?;
+
+    Ok(row.last_created_at)
+}
@@ -0,0 +1,119 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::{ReadNewsletters, SendNewsletters},
+    routes::{validate_send_at, Authorized, PublishError, ScheduledIssue},
+};
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleData {
+    send_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List scheduled newsletter issues", skip_all)]
+pub async fn list_scheduled_newsletters(
+    _reader: Authorized<ReadNewsletters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PublishError> {
+    let issues = get_scheduled_issues(&db_pool)
+        .await
+        .context("Failed to fetch the scheduled newsletter issues.")?;
+
+    Ok(HttpResponse::Ok().json(issues))
+}
+
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(_publisher, db_pool, body)
+)]
+pub async fn reschedule_newsletter(
+    _publisher: Authorized<SendNewsletters>,
+    db_pool: web::Data<PgPool>,
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Json<RescheduleData>,
+) -> Result<HttpResponse, PublishError> {
+    validate_send_at(body.send_at)?;
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET send_at = $2
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        *newsletter_issue_id,
+        body.send_at
+    )
+    .execute(db_pool.as_ref())
+    .await
+    .context("Failed to reschedule the newsletter issue.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(not_scheduled_error(&db_pool, *newsletter_issue_id).await);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+    name = "Cancel a scheduled newsletter issue",
+    skip(_publisher, db_pool)
+)]
+pub async fn cancel_scheduled_newsletter(
+    _publisher: Authorized<SendNewsletters>,
+    db_pool: web::Data<PgPool>,
+    newsletter_issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, PublishError> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        *newsletter_issue_id
+    )
+    .execute(db_pool.as_ref())
+    .await
+    .context("Failed to cancel the newsletter issue.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(not_scheduled_error(&db_pool, *newsletter_issue_id).await);
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get scheduled newsletter issues", skip(db_pool))]
+async fn get_scheduled_issues(db_pool: &PgPool) -> Result<Vec<ScheduledIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issue_id, title, send_at AS "send_at!"
+        FROM newsletter_issues
+        WHERE status = 'scheduled'
+        ORDER BY send_at
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+// Tells apart issues that do not exist from those that were already sent or cancelled
+async fn not_scheduled_error(db_pool: &PgPool, newsletter_issue_id: Uuid) -> PublishError {
+    let issue = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to fetch the status of the newsletter issue.");
+
+    match issue {
+        Ok(Some(_)) => PublishError::NotScheduled,
+        Ok(None) => PublishError::UnknownIssue,
+        Err(e) => PublishError::UnexpectedError(e),
+    }
+}
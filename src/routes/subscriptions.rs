@@ -1,4 +1,4 @@
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use askama_actix::Template;
 use chrono::Utc;
@@ -7,7 +7,9 @@ use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentEventType},
     email_client::EmailClient,
+    mailing_lists::{get_list_id, parse_list_slug},
     startup::ApplicationBaseUrl,
     types::{templates::ConfirmationEmailTemplate, Subscriber, SubscriberEmail, SubscriberName},
 };
@@ -16,6 +18,9 @@ use crate::{
 pub struct FormData {
     email: String,
     name: String,
+    list: Option<String>,
+    // Which form the request came from, kept as part of the proof of consent
+    source: Option<String>,
 }
 
 impl TryInto<Subscriber> for FormData {
@@ -30,7 +35,13 @@ impl TryInto<Subscriber> for FormData {
     }
 }
 
-fn generate_token() -> String {
+const DEFAULT_CONSENT_SOURCE: &str = "subscription_form";
+const MAX_CONSENT_SOURCE_LENGTH: usize = 100;
+
+// Confirmation links stop working after this long, a new one can be requested
+pub(crate) const SUBSCRIPTION_TOKEN_TTL_HOURS: i64 = 24;
+
+pub(crate) fn generate_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -63,32 +74,86 @@ impl ResponseError for SubscribeError {
 
 #[tracing::instrument(
     name = "Add new subscriber",
-    skip(form, db_pool, email_client, application_base_url),
+    skip(request, form, db_pool, email_client, application_base_url),
     fields(
         subscriber_name = %form.name,
         subscriber_email = %form.email
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     application_base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let list_slug = parse_list_slug(form.list.clone()).map_err(SubscribeError::ValidationError)?;
+    let source = form
+        .source
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CONSENT_SOURCE.to_string());
+    if source.chars().count() > MAX_CONSENT_SOURCE_LENGTH {
+        return Err(SubscribeError::ValidationError(format!(
+            "The form source cannot be longer than {} characters",
+            MAX_CONSENT_SOURCE_LENGTH
+        )));
+    }
+    let subscriber: Subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let list_id = get_list_id(&db_pool, &list_slug)
+        .await
+        .context("Failed to look up the mailing list.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "There is no mailing list called {}",
+                list_slug.as_ref()
+            ))
+        })?;
 
     let mut db_transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
-    let subscriber_id = insert_susbcriber_db(&mut db_transaction, &subscriber)
+    let (subscriber_id, suppression) = insert_susbcriber_db(&mut db_transaction, &subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    // Suppressed addresses get the same answer, so the form cannot be used to find them out
+    if let Some(suppression) = suppression {
+        tracing::info!(suppression = %suppression, "Skipped the confirmation of a suppressed address");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let status = insert_list_membership(&mut db_transaction, subscriber_id, list_id)
+        .await
+        .context("Failed to add the subscriber to the mailing list.")?;
+    if status != "pending_confirmation" {
+        if status != "confirmed" {
+            tracing::info!(status = %status, "Skipped the confirmation of a suppressed address");
+        }
+        return Ok(HttpResponse::Ok().finish());
+    }
 
     let subscriber_token = generate_token();
-    store_subscriber_token(&mut db_transaction, subscriber_id, &subscriber_token)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+    store_subscriber_token(
+        &mut db_transaction,
+        subscriber_id,
+        list_id,
+        &subscriber_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber.")?;
+    let consent_event = ConsentEvent {
+        subscriber_id,
+        list_id,
+        event_type: ConsentEventType::Subscribe,
+        token: Some(&subscriber_token),
+        source: &source,
+    };
+    record_consent_event(
+        &mut *db_transaction,
+        &consent_event,
+        &ConsentContext::from_request(&request),
+    )
+    .await
+    .context("Failed to record the consent of a new subscriber.")?;
 
     db_transaction
         .commit()
@@ -97,7 +162,7 @@ pub async fn subscribe(
 
     send_confirmation_email(
         &email_client,
-        subscriber,
+        &subscriber.email,
         &application_base_url.0,
         &subscriber_token,
     )
@@ -148,18 +213,25 @@ impl std::error::Error for StoreTokenError {
     name = "Store subscription token in the database",
     skip(subscription_token, db_transaction)
 )]
-async fn store_subscriber_token(
+pub(crate) async fn store_subscriber_token(
     db_transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
+    let created_at = Utc::now();
+    let expires_at = created_at + chrono::Duration::hours(SUBSCRIPTION_TOKEN_TTL_HOURS);
     let query = sqlx::query!(
         r#"
-            INSERT INTO subscription_tokens (subscription_token, subscriber_id) 
-            VALUES ($1, $2)
+            INSERT INTO subscription_tokens
+                (subscription_token, subscriber_id, list_id, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
         "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id,
+        created_at,
+        expires_at
     );
     db_transaction
         .execute(query)
@@ -176,37 +248,69 @@ async fn store_subscriber_token(
 async fn insert_susbcriber_db(
     db_transaction: &mut Transaction<'_, Postgres>,
     subscriber: &Subscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    let query = sqlx::query!(
+) -> Result<(Uuid, Option<String>), sqlx::Error> {
+    // The no-op update makes RETURNING yield the row of subscribers that already exist
+    let row = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
-        ON CONFLICT (email) 
-        DO UPDATE SET
-           id = EXCLUDED.id
-        WHERE subscriptions.status = 'pending_confirmation'
+        INSERT INTO subscriptions (id, email, name, subscribed_at, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (email)
+        DO UPDATE SET email = EXCLUDED.email
+        RETURNING id, suppression
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         subscriber.email.as_ref(),
         subscriber.name.as_ref(),
+        Utc::now(),
+        generate_token()
+    )
+    .fetch_one(&mut **db_transaction)
+    .await?;
+
+    Ok((row.id, row.suppression))
+}
+
+// Returns the status of the membership, only pending memberships need a confirmation email.
+// Confirmed ones have nothing left to do, bounced and complained ones must never be mailed again
+#[tracing::instrument(name = "Save list membership in the database", skip(db_transaction))]
+async fn insert_list_membership(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    // Subscribers that left can sign up again, going through the confirmation process once more
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', $3)
+        ON CONFLICT (subscriber_id, list_id)
+        DO UPDATE SET status = CASE
+            WHEN list_memberships.status IN ('confirmed', 'bounced', 'complained')
+                THEN list_memberships.status
+            ELSE 'pending_confirmation'
+        END
+        RETURNING status
+        "#,
+        subscriber_id,
+        list_id,
         Utc::now()
-    );
-    db_transaction.execute(query).await?;
+    )
+    .fetch_one(&mut **db_transaction)
+    .await?;
 
-    Ok(subscriber_id)
+    Ok(row.status)
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, subscriber, application_base_url, subscription_token)
+    skip(email_client, recipient, application_base_url, subscription_token)
 )]
-async fn send_confirmation_email(
+pub(crate) async fn send_confirmation_email(
     email_client: &EmailClient,
-    subscriber: Subscriber,
+    recipient: &SubscriberEmail,
     application_base_url: &String,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         application_base_url, subscription_token
@@ -225,6 +329,6 @@ async fn send_confirmation_email(
     );
 
     email_client
-        .send_email(&subscriber.email, "Welcome!", &html_body, &text_body)
+        .send_email(recipient, "Welcome!", &html_body, &text_body)
         .await
 }
@@ -0,0 +1,210 @@
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::ReadNewsletters,
+    routes::{error_chain_fmt, Authorized, PublishError},
+};
+
+// Transparent 1x1 GIF
+const TRACKING_PIXEL: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x01D\x00;";
+// Number of links reported in the stats of an issue
+const TOP_LINKS: i64 = 10;
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The tracking token is not valid.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TrackingError::UnknownToken => StatusCode::NOT_FOUND,
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueStats {
+    newsletter_issue_id: Uuid,
+    opens: i64,
+    unique_opens: i64,
+    clicks: i64,
+    unique_clicks: i64,
+    top_links: Vec<LinkStats>,
+}
+
+#[derive(serde::Serialize)]
+pub struct LinkStats {
+    url: String,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+// Mail clients get their pixel no matter what, failing to record the open is only logged
+#[tracing::instrument(name = "Track a newsletter issue open", skip_all)]
+pub async fn track_open(
+    db_pool: web::Data<PgPool>,
+    tracking_token: web::Path<String>,
+) -> HttpResponse {
+    if let Err(e) = record_open(&db_pool, &tracking_token).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record a newsletter issue open");
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(TRACKING_PIXEL)
+}
+
+#[tracing::instrument(name = "Track a newsletter issue link click", skip_all)]
+pub async fn track_click(
+    db_pool: web::Data<PgPool>,
+    tracking_token: web::Path<String>,
+) -> Result<HttpResponse, TrackingError> {
+    let url = record_click(&db_pool, &tracking_token)
+        .await
+        .context("Failed to record a link click.")?
+        .ok_or(TrackingError::UnknownToken)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+#[tracing::instrument(name = "Get the stats of a newsletter issue", skip(_reader, db_pool))]
+pub async fn newsletter_issue_stats(
+    _reader: Authorized<ReadNewsletters>,
+    db_pool: web::Data<PgPool>,
+    newsletter_issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, PublishError> {
+    let stats = get_issue_stats(&db_pool, *newsletter_issue_id)
+        .await
+        .context("Failed to compute the stats of the newsletter issue.")?
+        .ok_or(PublishError::UnknownIssue)?;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn record_open(db_pool: &PgPool, tracking_token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (tracking_token, occurred_at)
+        SELECT tracking_token, now()
+        FROM tracking_tokens
+        WHERE tracking_token = $1 AND link_id IS NULL
+        "#,
+        tracking_token
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok(())
+}
+
+// Returns the url behind the token, if there is one
+#[tracing::instrument(skip(db_pool))]
+async fn record_click(
+    db_pool: &PgPool,
+    tracking_token: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH event AS (
+            INSERT INTO tracking_events (tracking_token, occurred_at)
+            SELECT tracking_token, now()
+            FROM tracking_tokens
+            WHERE tracking_token = $1 AND link_id IS NOT NULL
+            RETURNING tracking_token
+        )
+        SELECT l.url
+        FROM event e
+        JOIN tracking_tokens t ON t.tracking_token = e.tracking_token
+        JOIN issue_links l ON l.link_id = t.link_id
+        "#,
+        tracking_token
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(row.map(|r| r.url))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn get_issue_stats(
+    db_pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueStats>, sqlx::Error> {
+    let issue_exists = sqlx::query!(
+        "SELECT 1 AS exists FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_optional(db_pool)
+    .await?
+    .is_some();
+    if !issue_exists {
+        return Ok(None);
+    }
+
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE t.link_id IS NULL) AS "opens!",
+            COUNT(DISTINCT t.subscriber_id) FILTER (WHERE t.link_id IS NULL) AS "unique_opens!",
+            COUNT(*) FILTER (WHERE t.link_id IS NOT NULL) AS "clicks!",
+            COUNT(DISTINCT t.subscriber_id) FILTER (WHERE t.link_id IS NOT NULL) AS "unique_clicks!"
+        FROM tracking_events e
+        JOIN tracking_tokens t ON t.tracking_token = e.tracking_token
+        WHERE t.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    let top_links = sqlx::query_as!(
+        LinkStats,
+        r#"
+        SELECT
+            l.url,
+            COUNT(*) AS "clicks!",
+            COUNT(DISTINCT t.subscriber_id) AS "unique_clicks!"
+        FROM tracking_events e
+        JOIN tracking_tokens t ON t.tracking_token = e.tracking_token
+        JOIN issue_links l ON l.link_id = t.link_id
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.url
+        ORDER BY 2 DESC, l.url
+        LIMIT $2
+        "#,
+        newsletter_issue_id,
+        TOP_LINKS
+    )
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(Some(IssueStats {
+        newsletter_issue_id,
+        opens: totals.opens,
+        unique_opens: totals.unique_opens,
+        clicks: totals.clicks,
+        unique_clicks: totals.unique_clicks,
+        top_links,
+    }))
+}
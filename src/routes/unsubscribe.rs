@@ -0,0 +1,187 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentEventType},
+    routes::error_chain_fmt,
+    types::{templates::UnsubscribeTemplate, ListSlug, UnsubscribeToken},
+};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    pub unsubscribe_token: String,
+    // Without a list the subscriber leaves every list they are part of
+    pub list: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+fn parse_list(list: Option<String>) -> Result<Option<ListSlug>, UnsubscribeError> {
+    list.map(ListSlug::parse)
+        .transpose()
+        .map_err(UnsubscribeError::ValidationError)
+}
+
+fn render_unsubscribe_page(unsubscribe_link: Option<&str>) -> Result<HttpResponse, anyhow::Error> {
+    let html_body = UnsubscribeTemplate { unsubscribe_link }
+        .render()
+        .context("Failed to render the unsubscribe page.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+// Following the link must not unsubscribe on its own, link scanners in mail servers would
+// otherwise unsubscribe people without them noticing. The page asks for an explicit POST
+#[tracing::instrument(name = "Show the unsubscribe page", skip(query_params, db_pool))]
+pub async fn unsubscribe_form(
+    query_params: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let list_slug = parse_list(query_params.list.clone())?;
+    let unsubscribe_token: UnsubscribeToken = query_params
+        .0
+        .try_into()
+        .map_err(UnsubscribeError::ValidationError)?;
+
+    get_subscriber_id(&db_pool, &unsubscribe_token)
+        .await
+        .context("Failed to get the token's associated subscriber id.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let mut unsubscribe_link = format!(
+        "/subscriptions/unsubscribe?unsubscribe_token={}",
+        unsubscribe_token.as_ref()
+    );
+    if let Some(list_slug) = list_slug {
+        unsubscribe_link.push_str(&format!("&list={}", list_slug.as_ref()));
+    }
+
+    Ok(render_unsubscribe_page(Some(&unsubscribe_link))?)
+}
+
+// Also the target of one-click unsubscribe requests (RFC 8058) sent by mail clients
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(request, query_params, db_pool)
+)]
+pub async fn unsubscribe(
+    request: HttpRequest,
+    query_params: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let list_slug = parse_list(query_params.list.clone())?;
+    let unsubscribe_token: UnsubscribeToken = query_params
+        .0
+        .try_into()
+        .map_err(UnsubscribeError::ValidationError)?;
+
+    let subscriber_id = get_subscriber_id(&db_pool, &unsubscribe_token)
+        .await
+        .context("Failed to get the token's associated subscriber id.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let list_ids =
+        mark_subscriber_as_unsubscribed(&mut db_transaction, subscriber_id, list_slug.as_ref())
+            .await
+            .context("Failed to update subscriber's status.")?;
+    let context = ConsentContext::from_request(&request);
+    for list_id in list_ids {
+        let consent_event = ConsentEvent {
+            subscriber_id,
+            list_id,
+            event_type: ConsentEventType::Unsubscribe,
+            token: Some(unsubscribe_token.as_ref()),
+            source: "unsubscribe_link",
+        };
+        record_consent_event(&mut *db_transaction, &consent_event, &context)
+            .await
+            .context("Failed to record the withdrawal of consent of a subscriber.")?;
+    }
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+
+    Ok(render_unsubscribe_page(None)?)
+}
+
+#[tracing::instrument(
+    name = "Get subscriber_id from unsubscribe_token",
+    skip(db_pool, unsubscribe_token)
+)]
+async fn get_subscriber_id(
+    db_pool: &PgPool,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE unsubscribe_token = $1",
+        unsubscribe_token.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(result.map(|r| r.id))
+}
+
+// Returns the lists the subscriber was still part of, leaving them again changes nothing.
+// Bounced and complained memberships keep their status, they must stay suppressed
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(db_transaction))]
+async fn mark_subscriber_as_unsubscribed(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_slug: Option<&ListSlug>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE
+            subscriber_id = $1 AND
+            status IN ('pending_confirmation', 'confirmed') AND
+            ($2::TEXT IS NULL OR list_id = (SELECT list_id FROM lists WHERE slug = $2))
+        RETURNING list_id
+        "#,
+        subscriber_id,
+        list_slug.map(|slug| slug.as_ref())
+    )
+    .fetch_all(&mut **db_transaction)
+    .await?;
+
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
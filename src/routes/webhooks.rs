@@ -0,0 +1,237 @@
+use actix_web::{
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::WebhookSettings,
+    routes::{basic_authentication, error_chain_fmt},
+    totp::constant_time_eq,
+};
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            WebhookError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+
+                response
+            }
+        }
+    }
+}
+
+// Postmark webhook payloads, only the fields we act upon are parsed, the rest is kept in the
+// audit trail
+#[derive(Deserialize)]
+#[serde(tag = "RecordType")]
+enum EmailEvent {
+    Bounce {
+        #[serde(rename = "Type")]
+        bounce_type: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    Delivery {
+        #[serde(rename = "Recipient")]
+        recipient: String,
+    },
+    // Opens, clicks and the like are tracked by ourselves
+    #[serde(other)]
+    Other,
+}
+
+impl EmailEvent {
+    fn record_type(&self) -> &'static str {
+        match self {
+            EmailEvent::Bounce { .. } => "bounce",
+            EmailEvent::SpamComplaint { .. } => "spam_complaint",
+            EmailEvent::Delivery { .. } => "delivery",
+            EmailEvent::Other => "other",
+        }
+    }
+
+    fn email(&self) -> Option<&str> {
+        match self {
+            EmailEvent::Bounce { email, .. } | EmailEvent::SpamComplaint { email } => Some(email),
+            EmailEvent::Delivery { recipient } => Some(recipient),
+            EmailEvent::Other => None,
+        }
+    }
+
+    // Membership status of the addresses we must stop mailing. Soft bounces are transient,
+    // the delivery worker already retries those
+    fn suppression_status(&self) -> Option<&'static str> {
+        match self {
+            EmailEvent::Bounce { bounce_type, .. }
+                if bounce_type == "HardBounce" || bounce_type == "BadEmailAddress" =>
+            {
+                Some("bounced")
+            }
+            EmailEvent::SpamComplaint { .. } => Some("complained"),
+            _ => None,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Ingest an email event",
+    skip_all,
+    fields(record_type = tracing::field::Empty)
+)]
+pub async fn email_events_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    db_pool: web::Data<PgPool>,
+    webhook: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate_provider(&request, &webhook)?;
+
+    let payload: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| WebhookError::ValidationError(format!("Invalid event payload: {}", e)))?;
+    let event = EmailEvent::deserialize(&payload)
+        .map_err(|e| WebhookError::ValidationError(format!("Invalid event payload: {}", e)))?;
+    tracing::Span::current().record("record_type", event.record_type());
+    let Some(email) = event.email() else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+
+    let mut db_transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    store_email_event(&mut db_transaction, event.record_type(), email, &payload)
+        .await
+        .context("Failed to store the email event.")?;
+    if let Some(status) = event.suppression_status() {
+        suppress_subscriber(&mut db_transaction, email, status)
+            .await
+            .context("Failed to update the status of the subscriber.")?;
+    }
+    db_transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an email event.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn authenticate_provider(
+    request: &HttpRequest,
+    webhook: &WebhookSettings,
+) -> Result<(), WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    // Both halves are always compared, so the timing does not reveal which one was wrong
+    let username_matches =
+        constant_time_eq(credentials.username.as_bytes(), webhook.username.as_bytes());
+    let password_matches = constant_time_eq(
+        credentials.password.expose_secret().as_bytes(),
+        webhook.password.expose_secret().as_bytes(),
+    );
+    if !(username_matches & password_matches) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )));
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_transaction, email, payload))]
+async fn store_email_event(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    record_type: &str,
+    email: &str,
+    payload: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_events (email_event_id, record_type, email, payload, received_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        record_type,
+        email,
+        payload,
+        Utc::now()
+    );
+    db_transaction.execute(query).await?;
+
+    Ok(())
+}
+
+// Takes the address out of every list it is on or joins later, so no issue is delivered to it anymore.
+// Outstanding confirmation links are dropped too, they would bring it back otherwise
+#[tracing::instrument(skip(db_transaction, email))]
+async fn suppress_subscriber(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE list_memberships m
+        SET status = $2
+        FROM subscriptions s
+        WHERE s.id = m.subscriber_id AND s.email = $1
+        "#,
+        email,
+        status
+    );
+    db_transaction.execute(query).await?;
+    let query = sqlx::query!(
+        "UPDATE subscriptions SET suppression = $2 WHERE email = $1",
+        email,
+        status
+    );
+    db_transaction.execute(query).await?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens t
+        USING subscriptions s
+        WHERE s.id = t.subscriber_id AND s.email = $1
+        "#,
+        email
+    );
+    db_transaction.execute(query).await?;
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::session_state::TypedSession;
+
+type SessionState = HashMap<String, String>;
+
+// Session state lives in Postgres, the cookie only carries the (signed) session key
+#[derive(Clone)]
+pub struct PgSessionStore {
+    db_pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+// Logs a user out from every device, e.g. once their account has been disabled
+#[tracing::instrument(name = "Delete all the sessions of a user", skip(db_pool))]
+pub async fn delete_user_sessions(db_pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    // Session values are stored as JSON encoded strings
+    let user_id = serde_json::to_string(&user_id)?;
+    sqlx::query!(
+        "DELETE FROM sessions WHERE state ->> $1 = $2",
+        TypedSession::USER_ID_KEY,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to delete the user's sessions.")?;
+
+    Ok(())
+}
+
+fn generate_session_key() -> SessionKey {
+    let mut rng = thread_rng();
+    let key: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect();
+
+    key.try_into()
+        .expect("A 64 characters key is always a valid session key")
+}
+
+fn expires_at(ttl: &Duration) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT state
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref()
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .context("Failed to load the session state.")
+        .map_err(LoadError::Other)?;
+
+        row.map(|r| serde_json::from_value(r.state))
+            .transpose()
+            .context("Failed to deserialize the session state.")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+
+        // Piggyback on new sessions to get rid of the expired ones
+        sqlx::query!("DELETE FROM sessions WHERE expires_at <= now()")
+            .execute(&self.db_pool)
+            .await
+            .context("Failed to delete expired sessions.")
+            .map_err(SaveError::Other)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to save the session state.")
+        .map_err(SaveError::Other)?;
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(UpdateError::Serialization)?;
+
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET state = $2, expires_at = $3
+            WHERE session_key = $1 AND expires_at > now()
+            "#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update the session state.")
+        .map_err(UpdateError::Other)?
+        .rows_affected();
+
+        // The session expired in the meantime, start a new one with the same state
+        if n_updated_rows == 0 {
+            return self.save(session_state, ttl).await.map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e),
+            });
+        }
+
+        Ok(session_key)
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &Duration,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            "UPDATE sessions SET expires_at = $2 WHERE session_key = $1",
+            session_key.as_ref(),
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update the session expiration.")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            "DELETE FROM sessions WHERE session_key = $1",
+            session_key.as_ref()
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to delete the session.")?;
+
+        Ok(())
+    }
+}
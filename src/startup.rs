@@ -1,37 +1,47 @@
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_session::{config::CookieContentSecurity, SessionMiddleware};
+use actix_web::{cookie::Key, dev::Server, web, App, HttpServer};
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
     configuration::{DatabaseSettings, Settings},
-    email_client::EmailClient,
-    routes::{confirm, health_check, publish_newsletter, subscribe},
+    newsletter_scheduler::run_scheduler_until_stopped,
+    rate_limiting::{RateLimiter, RateLimiting},
+    routes::{
+        admin_dashboard, cancel_scheduled_newsletter, change_password, change_password_form,
+        change_user_role, confirm, confirm_two_factor_enrolment, create_api_token, create_draft,
+        create_list, create_user, delete_draft, diff_draft_revisions, disable_two_factor,
+        disable_user, email_events_webhook, erase_own_personal_data, erase_subscriber_data,
+        export_own_personal_data, export_subscriber_consent, export_subscriber_data, get_draft,
+        get_draft_revision, get_newsletter_issue, get_two_factor_status, health_check,
+        list_api_tokens, list_draft_revisions, list_drafts, list_lists, list_newsletter_issues,
+        list_scheduled_newsletters, list_subscribers, list_users, log_out, login, login_form,
+        newsletter_issue_stats, preview_newsletter, publish_draft, publish_newsletter,
+        reschedule_newsletter, resend_confirmation, revoke_api_token, start_two_factor_enrolment,
+        subscribe, tag_subscriber, test_send_newsletter, track_click, track_open, unlock_user,
+        unsubscribe, unsubscribe_form, untag_subscriber, update_draft,
+    },
+    session_store::PgSessionStore,
 };
 
 pub struct Application {
     server: Server,
     port: u16,
+    db_pool: PgPool,
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct TestRecipients(pub Vec<String>);
+
+pub struct TombstoneSecret(pub SecretString);
+
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
         let db_pool = Self::get_db_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let http_client_timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            http_client_timeout,
-        );
-
         let address = format!(
             "{}:{}",
             configuration.application.address, configuration.application.port
@@ -39,14 +49,13 @@ impl Application {
         let listener = TcpListener::bind(address)?;
 
         let port = listener.local_addr().unwrap().port();
-        let server = Self::get_server(
-            listener,
-            db_pool,
-            email_client,
-            configuration.application.base_url,
-        )?;
+        let server = Self::get_server(listener, db_pool.clone(), configuration)?;
 
-        Ok(Self { server, port })
+        Ok(Self {
+            server,
+            port,
+            db_pool,
+        })
     }
 
     pub fn get_db_connection_pool(db_config: &DatabaseSettings) -> PgPool {
@@ -59,30 +68,191 @@ impl Application {
         self.port
     }
 
+    // Scheduled issues are published by the scheduler for as long as the API is running
     pub async fn run_application(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let scheduler = tokio::spawn(run_scheduler_until_stopped(self.db_pool));
+        let outcome = self.server.await;
+        scheduler.abort();
+
+        outcome
     }
 
     fn get_server(
         listener: TcpListener,
         db_pool: PgPool,
-        email_client: EmailClient,
-        base_url: String,
+        configuration: Settings,
     ) -> Result<Server, std::io::Error> {
+        let session_store = PgSessionStore::new(db_pool.clone());
+        let session_key = Key::from(
+            configuration
+                .application
+                .hmac_secret
+                .expose_secret()
+                .as_bytes(),
+        );
+        let tombstone_secret =
+            web::Data::new(TombstoneSecret(configuration.application.tombstone_secret));
+        // Confirmation emails are only sent through throttled routes
+        let rate_limiter = RateLimiter::from_settings(configuration.rate_limit, db_pool.clone());
         // Wrap the pool in web::Data, that ends up as an Arc pointer
         let db_connection = web::Data::new(db_pool);
-        let email_client = web::Data::new(email_client);
-        let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+        let webhook = web::Data::new(configuration.email_client.webhook.clone());
+        let test_recipients = web::Data::new(TestRecipients(
+            configuration.email_client.test_recipients.clone(),
+        ));
+        let email_client = web::Data::new(configuration.email_client.client());
+        let base_url = web::Data::new(ApplicationBaseUrl(configuration.application.base_url));
         let server = HttpServer::new(move || {
             App::new()
+                .wrap(
+                    SessionMiddleware::builder(session_store.clone(), session_key.clone())
+                        .cookie_content_security(CookieContentSecurity::Signed)
+                        .build(),
+                )
                 .wrap(TracingLogger::default())
                 .route("/health_check", web::get().to(health_check))
-                .route("/subscriptions", web::post().to(subscribe))
+                .route("/login", web::get().to(login_form))
+                .route("/login", web::post().to(login))
+                .route("/admin/dashboard", web::get().to(admin_dashboard))
+                .route("/admin/logout", web::post().to(log_out))
+                .route("/admin/password", web::get().to(change_password_form))
+                .route("/admin/password", web::post().to(change_password))
+                .route("/admin/lists", web::get().to(list_lists))
+                .route("/admin/lists", web::post().to(create_list))
+                .route("/admin/subscribers", web::get().to(list_subscribers))
+                .route(
+                    "/admin/subscribers/{subscriber_id}/consent",
+                    web::get().to(export_subscriber_consent),
+                )
+                .route(
+                    "/admin/subscribers/{subscriber_id}/tags",
+                    web::post().to(tag_subscriber),
+                )
+                .route(
+                    "/admin/subscribers/{subscriber_id}/tags/{tag}",
+                    web::delete().to(untag_subscriber),
+                )
+                .route("/admin/drafts", web::get().to(list_drafts))
+                .route("/admin/drafts", web::post().to(create_draft))
+                .route("/admin/drafts/{draft_id}", web::get().to(get_draft))
+                .route("/admin/drafts/{draft_id}", web::put().to(update_draft))
+                .route("/admin/drafts/{draft_id}", web::delete().to(delete_draft))
+                .route(
+                    "/admin/drafts/{draft_id}/revisions",
+                    web::get().to(list_draft_revisions),
+                )
+                .route(
+                    "/admin/drafts/{draft_id}/revisions/{revision}",
+                    web::get().to(get_draft_revision),
+                )
+                .route(
+                    "/admin/drafts/{draft_id}/diff",
+                    web::get().to(diff_draft_revisions),
+                )
+                .route(
+                    "/admin/drafts/{draft_id}/publish",
+                    web::post().to(publish_draft),
+                )
+                .route(
+                    "/admin/personal-data",
+                    web::get().to(export_subscriber_data),
+                )
+                .route(
+                    "/admin/personal-data",
+                    web::delete().to(erase_subscriber_data),
+                )
+                .route("/admin/api-tokens", web::get().to(list_api_tokens))
+                .route("/admin/api-tokens", web::post().to(create_api_token))
+                .route(
+                    "/admin/api-tokens/{api_token_id}",
+                    web::delete().to(revoke_api_token),
+                )
+                .route("/admin/two-factor", web::get().to(get_two_factor_status))
+                .route(
+                    "/admin/two-factor",
+                    web::post().to(start_two_factor_enrolment),
+                )
+                .route("/admin/two-factor", web::delete().to(disable_two_factor))
+                .route(
+                    "/admin/two-factor/confirm",
+                    web::post().to(confirm_two_factor_enrolment),
+                )
+                .route("/admin/users", web::get().to(list_users))
+                .route("/admin/users", web::post().to(create_user))
+                .route(
+                    "/admin/users/{user_id}/disable",
+                    web::post().to(disable_user),
+                )
+                .route(
+                    "/admin/users/{user_id}/role",
+                    web::put().to(change_user_role),
+                )
+                .route("/admin/users/{user_id}/unlock", web::post().to(unlock_user))
+                .service(
+                    web::resource("/subscriptions")
+                        .wrap(RateLimiting::new(rate_limiter.clone()))
+                        .route(web::post().to(subscribe)),
+                )
                 .route("/subscriptions/confirm", web::get().to(confirm))
+                .service(
+                    web::resource("/subscriptions/resend-confirmation")
+                        .wrap(RateLimiting::new(rate_limiter.clone()))
+                        .route(web::post().to(resend_confirmation)),
+                )
+                .route(
+                    "/subscriptions/unsubscribe",
+                    web::get().to(unsubscribe_form),
+                )
+                .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+                .route(
+                    "/subscriptions/personal-data",
+                    web::get().to(export_own_personal_data),
+                )
+                .route(
+                    "/subscriptions/personal-data",
+                    web::delete().to(erase_own_personal_data),
+                )
                 .route("/newsletters", web::post().to(publish_newsletter))
+                .route("/newsletters", web::get().to(list_newsletter_issues))
+                .route("/newsletters/preview", web::post().to(preview_newsletter))
+                .route(
+                    "/newsletters/test-send",
+                    web::post().to(test_send_newsletter),
+                )
+                // Registered before the archive routes, which would take "scheduled" for an id
+                .route(
+                    "/newsletters/scheduled",
+                    web::get().to(list_scheduled_newsletters),
+                )
+                .route(
+                    "/newsletters/scheduled/{newsletter_issue_id}/reschedule",
+                    web::post().to(reschedule_newsletter),
+                )
+                .route(
+                    "/newsletters/scheduled/{newsletter_issue_id}/cancel",
+                    web::post().to(cancel_scheduled_newsletter),
+                )
+                .route(
+                    "/newsletters/{newsletter_issue_id}",
+                    web::get().to(get_newsletter_issue),
+                )
+                .route(
+                    "/newsletters/{newsletter_issue_id}/stats",
+                    web::get().to(newsletter_issue_stats),
+                )
+                .route("/t/o/{tracking_token}", web::get().to(track_open))
+                .route("/t/c/{tracking_token}", web::get().to(track_click))
+                .route(
+                    "/webhooks/email-events",
+                    web::post().to(email_events_webhook),
+                )
                 .app_data(db_connection.clone())
                 .app_data(email_client.clone())
                 .app_data(base_url.clone())
+                .app_data(webhook.clone())
+                .app_data(test_recipients.clone())
+                .app_data(tombstone_secret.clone())
+                .app_data(web::Data::from(rate_limiter.clone()))
         })
         .listen(listener)?
         .run();
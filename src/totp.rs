@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+// RFC 6238 defaults, the only parameters every authenticator app supports
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_BYTES: usize = 20;
+// Codes of the neighbouring steps are accepted too, clocks drift
+const ALLOWED_SKEW_STEPS: i64 = 1;
+
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+pub fn time_step(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(STEP_SECONDS)
+}
+
+// The HOTP value (RFC 4226) of a time step
+fn code_at_step(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = usize::from(digest[digest.len() - 1] & 0x0f);
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+pub fn code_at(secret: &str, now: DateTime<Utc>) -> Result<String, anyhow::Error> {
+    Ok(code_at_step(&decode_secret(secret)?, time_step(now)))
+}
+
+// The time step a code was generated for, if it is valid around `now`
+pub fn matching_step(
+    secret: &str,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<i64>, anyhow::Error> {
+    let secret = decode_secret(secret)?;
+    let code = code.trim();
+    let current_step = time_step(now);
+
+    Ok(
+        (current_step - ALLOWED_SKEW_STEPS..=current_step + ALLOWED_SKEW_STEPS).find(|step| {
+            constant_time_eq(code_at_step(&secret, *step).as_bytes(), code.as_bytes())
+        }),
+    )
+}
+
+// What authenticator apps scan from the QR code
+pub fn provisioning_uri(secret: &str, issuer: &str, account: &str) -> String {
+    let issuer = urlencoding::encode(issuer);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        urlencoding::encode(account),
+        secret,
+        issuer,
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>, anyhow::Error> {
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .map_err(|e| anyhow::anyhow!("The TOTP secret is not valid base32: {}", e))
+}
+
+// Compares secrets without leaking through timing how much of them matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // The SHA1 test secret of RFC 6238, "12345678901234567890"
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn test_codes_match_the_rfc_6238_test_vectors() {
+        // The RFC lists 8 digit codes, 6 digit codes are their last 6 digits
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+
+        for (timestamp, expected) in vectors {
+            assert_eq!(code_at(RFC_SECRET, at(timestamp)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_codes_of_neighbouring_steps_are_accepted() {
+        let now = at(1111111111);
+        let step = time_step(now);
+
+        for offset in [-30, 0, 30] {
+            let code = code_at(RFC_SECRET, at(1111111111 + offset)).unwrap();
+            let matched = matching_step(RFC_SECRET, &code, now).unwrap();
+            assert_eq!(matched, Some(step + offset / 30));
+        }
+    }
+
+    #[test]
+    fn test_stale_and_wrong_codes_are_rejected() {
+        let now = at(1111111111);
+        let stale_code = code_at(RFC_SECRET, at(1111111111 - 90)).unwrap();
+
+        assert_eq!(matching_step(RFC_SECRET, &stale_code, now).unwrap(), None);
+        assert_eq!(matching_step(RFC_SECRET, "000000x", now).unwrap(), None);
+    }
+
+    #[test]
+    fn test_generated_secrets_decode_to_160_bits() {
+        let secret = generate_secret();
+
+        assert_eq!(decode_secret(&secret).unwrap().len(), SECRET_BYTES);
+    }
+
+    #[test]
+    fn test_provisioning_uri_escapes_the_account_name() {
+        let uri = provisioning_uri(RFC_SECRET, "zero2prod", "jane doe");
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/zero2prod:jane%20doe?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+            &issuer=zero2prod&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
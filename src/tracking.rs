@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use sqlx::{Executor, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::routes::generate_token;
+
+// Locations of the http(s) targets of every `href` attribute in `html`
+fn link_spans(html: &str) -> Vec<Range<usize>> {
+    let lowercase_html = html.to_ascii_lowercase();
+    let mut spans = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = lowercase_html[position..].find("href=") {
+        let value_start = position + offset + "href=".len();
+        position = value_start;
+        let Some(quote) = html[value_start..].chars().next() else {
+            break;
+        };
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let Some(length) = html[value_start + 1..].find(quote) else {
+            break;
+        };
+        let span = value_start + 1..value_start + 1 + length;
+        position = span.end;
+        let target = &lowercase_html[span.clone()];
+        // Targets with placeholders are different for every recipient, they are not tracked
+        if (target.starts_with("http://") || target.starts_with("https://"))
+            && !target.contains("{{")
+        {
+            spans.push(span);
+        }
+    }
+    spans
+}
+
+// Attribute values escape ampersands, the link points to the unescaped url
+fn unescape_url(value: &str) -> String {
+    value.replace("&amp;", "&")
+}
+
+// Unique links of an issue, in order of appearance
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for span in link_spans(html) {
+        let url = unescape_url(&html[span]);
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+// Replaces the target of every link for which `rewrite` returns a new one
+pub fn rewrite_links(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut position = 0;
+    for span in link_spans(html) {
+        if let Some(target) = rewrite(&unescape_url(&html[span.clone()])) {
+            rewritten.push_str(&html[position..span.start]);
+            rewritten.push_str(&target);
+            position = span.end;
+        }
+    }
+    rewritten.push_str(&html[position..]);
+    rewritten
+}
+
+#[tracing::instrument(
+    name = "Store the links of a newsletter issue",
+    skip(db_transaction, html)
+)]
+pub async fn store_issue_links(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    html: &str,
+) -> Result<(), sqlx::Error> {
+    let links = extract_links(html);
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let mut query =
+        QueryBuilder::new("INSERT INTO issue_links (link_id, newsletter_issue_id, url) ");
+    query.push_values(links, |mut row, url| {
+        row.push_bind(Uuid::new_v4())
+            .push_bind(newsletter_issue_id)
+            .push_bind(url);
+    });
+    db_transaction.execute(query.build()).await?;
+
+    Ok(())
+}
+
+// Personalises the html of an issue for one subscriber: links redirect through the click tracker
+// and a pixel reports opens
+#[tracing::instrument(
+    name = "Add tracking to a newsletter issue",
+    skip(db_transaction, base_url, html)
+)]
+pub async fn add_tracking(
+    db_transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    base_url: &str,
+    html: &str,
+) -> Result<String, sqlx::Error> {
+    let links = sqlx::query!(
+        "SELECT link_id, url FROM issue_links WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_all(&mut **db_transaction)
+    .await?;
+
+    let open_token = generate_token();
+    let click_tokens: HashMap<String, (Uuid, String)> = links
+        .into_iter()
+        .map(|link| (link.url, (link.link_id, generate_token())))
+        .collect();
+
+    let mut query = QueryBuilder::new(
+        "INSERT INTO tracking_tokens (tracking_token, newsletter_issue_id, subscriber_id, link_id) ",
+    );
+    let tokens = std::iter::once((&open_token, None)).chain(
+        click_tokens
+            .values()
+            .map(|(link_id, token)| (token, Some(*link_id))),
+    );
+    query.push_values(tokens, |mut row, (token, link_id)| {
+        row.push_bind(token.clone())
+            .push_bind(newsletter_issue_id)
+            .push_bind(subscriber_id)
+            .push_bind(link_id);
+    });
+    db_transaction.execute(query.build()).await?;
+
+    let html = rewrite_links(html, |url| {
+        click_tokens
+            .get(url)
+            .map(|(_, token)| format!("{}/t/c/{}", base_url, token))
+    });
+    Ok(format!(
+        "{}<img src=\"{}/t/o/{}\" width=\"1\" height=\"1\" alt=\"\">",
+        html, base_url, open_token
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_http_links_are_extracted_once() {
+        let html = r#"<a href="https://example.com/a">A</a>
+            <a HREF='http://example.com/b?x=1&amp;y=2'>B</a>
+            <a href="mailto:someone@example.com">Mail</a>
+            <a href="https://example.com/a">A again</a>"#;
+
+        assert_eq!(
+            extract_links(html),
+            vec!["https://example.com/a", "http://example.com/b?x=1&y=2"]
+        );
+    }
+
+    #[test]
+    fn test_links_with_placeholders_are_not_extracted() {
+        let html = r#"<a href="https://example.com/?ref={{ email }}">A</a>
+            <a href="{{ unsubscribe_url }}">Leave</a>"#;
+
+        assert!(extract_links(html).is_empty());
+    }
+
+    #[test]
+    fn test_links_are_rewritten_in_place() {
+        let html = r#"<p>Read <a href="https://example.com/a">this</a> and <a href="/relative">that</a></p>"#;
+
+        let rewritten = rewrite_links(html, |url| Some(format!("https://tracker/{}", url.len())));
+
+        assert_eq!(
+            rewritten,
+            r#"<p>Read <a href="https://tracker/21">this</a> and <a href="/relative">that</a></p>"#
+        );
+    }
+
+    #[test]
+    fn test_links_without_a_replacement_are_kept() {
+        let html = r#"<a href="https://example.com">Link</a>"#;
+
+        assert_eq!(rewrite_links(html, |_| None), html);
+    }
+
+    #[test]
+    fn test_unterminated_attributes_are_ignored() {
+        let html = r#"<a href="https://example.com>Link</a>"#;
+
+        assert!(extract_links(html).is_empty());
+        assert_eq!(rewrite_links(html, |_| Some("x".into())), html);
+    }
+}
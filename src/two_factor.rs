@@ -0,0 +1,325 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::{routes::error_chain_fmt, totp};
+
+const RECOVERY_CODES: usize = 10;
+
+#[derive(thiserror::Error)]
+pub enum TwoFactorError {
+    #[error("A two-factor code is required.")]
+    Required,
+    #[error("The two-factor code is invalid.")]
+    InvalidCode,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TwoFactorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+// A fresh enrolment, waiting for its first code
+pub struct PendingEnrolment {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+// Lowercase so that they can be read out and typed in without ambiguity on the case
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+#[tracing::instrument(name = "Is two-factor enabled", skip(executor))]
+pub async fn is_enrolled(
+    executor: impl Executor<'_, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM two_factor_enrolments
+            WHERE user_id = $1 AND confirmed_at IS NOT NULL
+        ) AS "enrolled!"
+        "#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to look up the two-factor enrolment.")?;
+
+    Ok(row.enrolled)
+}
+
+// Replaces any enrolment that was never confirmed. Returns None when the user is already enrolled
+#[tracing::instrument(name = "Start two-factor enrolment", skip(db_pool))]
+pub async fn start_enrolment(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    issuer: &str,
+    account: &str,
+) -> Result<Option<PendingEnrolment>, anyhow::Error> {
+    let secret = totp::generate_secret();
+    let n_upserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO two_factor_enrolments (user_id, secret, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (user_id) DO UPDATE SET
+            secret = EXCLUDED.secret,
+            created_at = EXCLUDED.created_at
+        WHERE two_factor_enrolments.confirmed_at IS NULL
+        "#,
+        user_id,
+        secret
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the two-factor enrolment.")?
+    .rows_affected();
+    if n_upserted_rows == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(PendingEnrolment {
+        provisioning_uri: totp::provisioning_uri(&secret, issuer, account),
+        secret,
+    }))
+}
+
+// Enables the second factor once the user proves their authenticator works, returning the
+// recovery codes. They are never shown again
+#[tracing::instrument(name = "Confirm two-factor enrolment", skip(db_pool, code))]
+pub async fn confirm_enrolment(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: &SecretString,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, TwoFactorError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let enrolment = sqlx::query!(
+        r#"
+        SELECT secret
+        FROM two_factor_enrolments
+        WHERE user_id = $1 AND confirmed_at IS NULL
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the pending two-factor enrolment.")?
+    .ok_or(TwoFactorError::Required)?;
+
+    let step = totp::matching_step(&enrolment.secret, code.expose_secret(), now)?
+        .ok_or(TwoFactorError::InvalidCode)?;
+    sqlx::query!(
+        r#"
+        UPDATE two_factor_enrolments
+        SET confirmed_at = now(), last_used_step = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        step
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to confirm the two-factor enrolment.")?;
+
+    let recovery_codes: Vec<String> = std::iter::repeat_with(generate_recovery_code)
+        .take(RECOVERY_CODES)
+        .collect();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        "DELETE FROM two_factor_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the previous recovery codes.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO two_factor_recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::TEXT[])
+        "#,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the recovery codes.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a two-factor enrolment.")?;
+
+    Ok(recovery_codes)
+}
+
+// Users that are not enrolled pass without a code. Enrolled users need a code of the current time
+// step that was not used before, or an unused recovery code
+#[tracing::instrument(name = "Verify second factor", skip(db_pool, code))]
+pub async fn verify_second_factor(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: Option<&SecretString>,
+    now: DateTime<Utc>,
+) -> Result<(), TwoFactorError> {
+    let Some(enrolment) = sqlx::query!(
+        r#"
+        SELECT secret
+        FROM two_factor_enrolments
+        WHERE user_id = $1 AND confirmed_at IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up the two-factor enrolment.")?
+    else {
+        return Ok(());
+    };
+    let code = code
+        .map(|code| code.expose_secret().trim())
+        .filter(|code| !code.is_empty())
+        .ok_or(TwoFactorError::Required)?;
+
+    if let Some(step) = totp::matching_step(&enrolment.secret, code, now)? {
+        // Concurrent requests with the same code cannot both move the step forward
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE two_factor_enrolments
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to record the use of a two-factor code.")?
+        .rows_affected();
+        if n_updated_rows == 0 {
+            tracing::warn!(
+                security.event = "two_factor_code_replayed",
+                security.user_id = %user_id,
+                "A two-factor code was used twice"
+            );
+            return Err(TwoFactorError::InvalidCode);
+        }
+        return Ok(());
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE two_factor_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to use a recovery code.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(TwoFactorError::InvalidCode);
+    }
+    tracing::warn!(
+        security.event = "recovery_code_used",
+        security.user_id = %user_id,
+        "Logged in with a recovery code"
+    );
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Count unused recovery codes", skip(db_pool))]
+pub async fn unused_recovery_codes(db_pool: &PgPool, user_id: Uuid) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM two_factor_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to count the recovery codes.")?;
+
+    Ok(row.count)
+}
+
+#[tracing::instrument(name = "Disable two-factor", skip(db_pool))]
+pub async fn disable(db_pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        "DELETE FROM two_factor_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the recovery codes.")?;
+    sqlx::query!(
+        "DELETE FROM two_factor_enrolments WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the two-factor enrolment.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_codes_are_two_groups_of_five() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_eq!(code, code.to_lowercase());
+    }
+
+    #[test]
+    fn test_recovery_code_hashes_ignore_case_and_spaces() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE-12345 ")
+        );
+    }
+}
@@ -0,0 +1,39 @@
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    pub fn parse(key: String) -> Result<IdempotencyKey, String> {
+        if key.trim().is_empty() {
+            return Err("The idempotency key cannot be empty".into());
+        }
+        if key.len() > 50 {
+            return Err("The idempotency key cannot be longer than 50 characters".into());
+        }
+
+        Ok(IdempotencyKey(key))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn test_empty_key_is_rejected() {
+        assert_err!(IdempotencyKey::parse("  ".to_string()));
+    }
+
+    #[test]
+    fn test_key_max_length_50() {
+        assert_ok!(IdempotencyKey::parse("a".repeat(50)));
+        assert_err!(IdempotencyKey::parse("a".repeat(51)));
+    }
+}
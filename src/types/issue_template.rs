@@ -0,0 +1,172 @@
+// Title or body of a newsletter issue, with placeholders that are filled in for every recipient,
+// e.g. `Hi {{ name }}!`
+#[derive(Debug)]
+pub struct IssueTemplate(Vec<Part>);
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Placeholder {
+    Name,
+    Email,
+    UnsubscribeUrl,
+}
+
+pub struct RecipientDetails<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+impl RecipientDetails<'_> {
+    // Used where an issue is shown to nobody in particular, like the public archive
+    pub fn anonymous() -> Self {
+        RecipientDetails {
+            name: "",
+            email: "",
+            unsubscribe_url: "",
+        }
+    }
+
+    fn value(&self, placeholder: Placeholder) -> &str {
+        match placeholder {
+            Placeholder::Name => self.name,
+            Placeholder::Email => self.email,
+            Placeholder::UnsubscribeUrl => self.unsubscribe_url,
+        }
+    }
+}
+
+impl IssueTemplate {
+    pub fn parse(source: &str) -> Result<IssueTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let after_opening = &rest[start + 2..];
+            let end = after_opening
+                .find("}}")
+                .ok_or_else(|| "A placeholder is missing its closing braces".to_string())?;
+            let placeholder = match after_opening[..end].trim() {
+                "name" => Placeholder::Name,
+                "email" => Placeholder::Email,
+                "unsubscribe_url" => Placeholder::UnsubscribeUrl,
+                other => {
+                    return Err(format!(
+                    "{{{{ {} }}}} is not a valid placeholder, use name, email or unsubscribe_url",
+                    other
+                ))
+                }
+            };
+            parts.push(Part::Placeholder(placeholder));
+            rest = &after_opening[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(IssueTemplate(parts))
+    }
+
+    pub fn render_text(&self, recipient: &RecipientDetails) -> String {
+        self.render(|placeholder| recipient.value(placeholder).to_string())
+    }
+
+    // Recipient details are untrusted input, they are escaped before landing in the html
+    pub fn render_html(&self, recipient: &RecipientDetails) -> String {
+        self.render(|placeholder| escape_html(recipient.value(placeholder)))
+    }
+
+    fn render(&self, value: impl Fn(Placeholder) -> String) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Placeholder(placeholder) => value(*placeholder),
+            })
+            .collect()
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use super::*;
+
+    fn recipient() -> RecipientDetails<'static> {
+        RecipientDetails {
+            name: "Ursula <b>Le Guin</b>",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=a&list=b",
+        }
+    }
+
+    #[test]
+    fn test_placeholders_are_filled_in() {
+        let template =
+            IssueTemplate::parse("Hi {{ name }} ({{email}}), leave at {{  unsubscribe_url }}")
+                .unwrap();
+
+        assert_eq!(
+            template.render_text(&recipient()),
+            "Hi Ursula <b>Le Guin</b> (ursula@example.com), leave at https://example.com/unsubscribe?token=a&list=b"
+        );
+    }
+
+    #[test]
+    fn test_html_values_are_escaped() {
+        let template =
+            IssueTemplate::parse("<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">").unwrap();
+
+        assert_eq!(
+            template.render_html(&recipient()),
+            "<p>Hi Ursula &lt;b&gt;Le Guin&lt;/b&gt;</p><a href=\"https://example.com/unsubscribe?token=a&amp;list=b\">"
+        );
+    }
+
+    #[test]
+    fn test_text_without_placeholders_is_unchanged() {
+        let source = "<p>Just text, with a stray }} brace</p>";
+
+        assert_eq!(
+            IssueTemplate::parse(source)
+                .unwrap()
+                .render_html(&recipient()),
+            source
+        );
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ surname }}"));
+        assert_err!(IssueTemplate::parse("Hi {{}}"));
+    }
+
+    #[test]
+    fn test_unclosed_placeholders_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ name"));
+        assert_err!(IssueTemplate::parse("Hi {{ name }"));
+    }
+}
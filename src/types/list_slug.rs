@@ -0,0 +1,60 @@
+// Short identifier of a mailing list, used in forms, payloads and links
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    // Everybody who subscribed before lists existed belongs to this one
+    pub const DEFAULT: &'static str = "default";
+
+    pub fn parse(slug: String) -> Result<ListSlug, String> {
+        let is_valid_character = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if slug.is_empty() || slug.len() > 50 || !slug.chars().all(is_valid_character) {
+            return Err(format!("{} is not a valid list identifier!", slug));
+        }
+
+        Ok(ListSlug(slug))
+    }
+}
+
+impl Default for ListSlug {
+    fn default() -> Self {
+        ListSlug(Self::DEFAULT.to_string())
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn test_lowercase_alphanumeric_slugs_with_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".into()));
+        assert_ok!(ListSlug::parse(ListSlug::DEFAULT.into()));
+    }
+
+    #[test]
+    fn test_empty_slug_is_rejected() {
+        assert_err!(ListSlug::parse("".into()));
+    }
+
+    #[test]
+    fn test_slug_longer_than_50_characters_is_rejected() {
+        assert_ok!(ListSlug::parse("a".repeat(50)));
+        assert_err!(ListSlug::parse("a".repeat(51)));
+    }
+
+    #[test]
+    fn test_slug_with_invalid_characters_is_rejected() {
+        for slug in ["Rust", "rust weekly", "rust_weekly", "rüst"] {
+            assert_err!(ListSlug::parse(slug.into()));
+        }
+    }
+}
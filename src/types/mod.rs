@@ -1,10 +1,24 @@
+mod idempotency_key;
+mod issue_template;
+mod list_slug;
+mod new_password;
+mod segment;
 mod subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 mod subscription_token;
 pub mod templates;
+mod unsubscribe_token;
 
+pub use idempotency_key::IdempotencyKey;
+pub use issue_template::{IssueTemplate, RecipientDetails};
+pub use list_slug::ListSlug;
+pub use new_password::NewPassword;
+pub use segment::Segment;
 pub use subscriber::Subscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
 pub use subscription_token::SubscriptionToken;
+pub use unsubscribe_token::UnsubscribeToken;
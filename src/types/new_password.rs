@@ -0,0 +1,48 @@
+use secrecy::{ExposeSecret, SecretString};
+use unicode_segmentation::UnicodeSegmentation;
+
+const MIN_PASSWORD_LENGTH: usize = 12;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+#[derive(Debug)]
+pub struct NewPassword(SecretString);
+
+impl NewPassword {
+    pub fn parse(password: SecretString) -> Result<NewPassword, String> {
+        let length = password.expose_secret().graphemes(true).count();
+        if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&length) {
+            return Err(format!(
+                "The new password must be between {} and {} characters long.",
+                MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+            ));
+        }
+
+        Ok(NewPassword(password))
+    }
+}
+
+impl From<NewPassword> for SecretString {
+    fn from(password: NewPassword) -> Self {
+        password.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::*;
+
+    #[test]
+    fn test_password_min_length_12() {
+        assert_ok!(NewPassword::parse(Secret::new("a".repeat(12))));
+        assert_err!(NewPassword::parse(Secret::new("a".repeat(11))));
+    }
+
+    #[test]
+    fn test_password_max_length_128() {
+        assert_ok!(NewPassword::parse(Secret::new("ё".repeat(128))));
+        assert_err!(NewPassword::parse(Secret::new("ё".repeat(129))));
+    }
+}
@@ -0,0 +1,199 @@
+use crate::types::SubscriberTag;
+
+// Keeps the generated SQL, and the recursion needed to parse nested expressions, bounded
+const MAX_EXPRESSION_LENGTH: usize = 500;
+
+// Boolean expression over subscriber tags, e.g. `beta AND (eu OR NOT paid)`. NOT binds tighter
+// than AND, which binds tighter than OR
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    Not,
+    Tag(SubscriberTag),
+}
+
+impl Segment {
+    pub fn parse(expression: &str) -> Result<Segment, String> {
+        if expression.len() > MAX_EXPRESSION_LENGTH {
+            return Err(format!(
+                "Segment expressions cannot be longer than {} characters",
+                MAX_EXPRESSION_LENGTH
+            ));
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            position: 0,
+        };
+        let segment = parser.parse_or()?;
+        if parser.position < parser.tokens.len() {
+            return Err("Unexpected token after the end of the segment expression".into());
+        }
+
+        Ok(segment)
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::OpenParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::CloseParen);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Tag(SubscriberTag::parse(word)?),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next_is(&self, token: &Token) -> bool {
+        self.tokens.get(self.position) == Some(token)
+    }
+
+    fn parse_or(&mut self) -> Result<Segment, String> {
+        let mut segment = self.parse_and()?;
+        while self.next_is(&Token::Or) {
+            self.position += 1;
+            segment = Segment::Or(Box::new(segment), Box::new(self.parse_and()?));
+        }
+
+        Ok(segment)
+    }
+
+    fn parse_and(&mut self) -> Result<Segment, String> {
+        let mut segment = self.parse_not()?;
+        while self.next_is(&Token::And) {
+            self.position += 1;
+            segment = Segment::And(Box::new(segment), Box::new(self.parse_not()?));
+        }
+
+        Ok(segment)
+    }
+
+    fn parse_not(&mut self) -> Result<Segment, String> {
+        if self.next_is(&Token::Not) {
+            self.position += 1;
+            return Ok(Segment::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Segment, String> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        match token {
+            Some(Token::Tag(tag)) => Ok(Segment::Tag(tag.clone())),
+            Some(Token::OpenParen) => {
+                let segment = self.parse_or()?;
+                if !self.next_is(&Token::CloseParen) {
+                    return Err("Missing closing parenthesis in the segment expression".into());
+                }
+                self.position += 1;
+                Ok(segment)
+            }
+            Some(_) => {
+                Err("Expected a tag or an opening parenthesis in the segment expression".into())
+            }
+            None => Err("The segment expression ended unexpectedly".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    fn tag(tag: &str) -> Box<Segment> {
+        Box::new(Segment::Tag(SubscriberTag::parse(tag.into()).unwrap()))
+    }
+
+    #[test]
+    fn test_single_tag_is_a_valid_segment() {
+        assert_eq!(Segment::parse("beta").unwrap(), *tag("beta"));
+    }
+
+    #[test]
+    fn test_and_binds_tighter_than_or() {
+        assert_eq!(
+            Segment::parse("beta OR paid AND eu").unwrap(),
+            Segment::Or(tag("beta"), Box::new(Segment::And(tag("paid"), tag("eu"))))
+        );
+    }
+
+    #[test]
+    fn test_parentheses_and_not_are_supported() {
+        assert_eq!(
+            Segment::parse("NOT (beta OR paid)").unwrap(),
+            Segment::Not(Box::new(Segment::Or(tag("beta"), tag("paid"))))
+        );
+        assert_ok!(Segment::parse("(beta AND NOT NOT eu) OR (paid)"));
+    }
+
+    #[test]
+    fn test_malformed_expressions_are_rejected() {
+        for expression in [
+            "",
+            "beta AND",
+            "OR beta",
+            "(beta OR paid",
+            "beta paid",
+            "beta )",
+            "Beta",
+            "beta && paid",
+        ] {
+            assert_err!(Segment::parse(expression));
+        }
+    }
+
+    #[test]
+    fn test_too_long_expressions_are_rejected() {
+        let expression = vec!["beta"; 101].join(" OR ");
+        assert_err!(Segment::parse(&expression));
+    }
+}
@@ -0,0 +1,47 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(tag: String) -> Result<SubscriberTag, String> {
+        let is_valid_character =
+            |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+        if tag.is_empty() || tag.len() > 50 || !tag.chars().all(is_valid_character) {
+            return Err(format!("{} is not a valid tag!", tag));
+        }
+
+        Ok(SubscriberTag(tag))
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn test_lowercase_tags_are_valid() {
+        for tag in ["beta", "paid", "eu", "early_adopter", "tier-2"] {
+            assert_ok!(SubscriberTag::parse(tag.into()));
+        }
+    }
+
+    #[test]
+    fn test_empty_or_too_long_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("".into()));
+        assert_err!(SubscriberTag::parse("a".repeat(51)));
+    }
+
+    #[test]
+    fn test_tags_with_uppercase_or_whitespace_are_rejected() {
+        for tag in ["Beta", "AND", "two words", "(eu)"] {
+            assert_err!(SubscriberTag::parse(tag.into()));
+        }
+    }
+}
@@ -1,3 +1,17 @@
+mod admin_dashboard_template;
+mod change_password_template;
 mod confirmation_email_template;
+mod login_template;
+mod newsletter_email_template;
+mod newsletter_issue_template;
+mod newsletter_issues_template;
+mod unsubscribe_template;
 
+pub use admin_dashboard_template::AdminDashboardTemplate;
+pub use change_password_template::ChangePasswordTemplate;
 pub use confirmation_email_template::ConfirmationEmailTemplate;
+pub use login_template::LoginTemplate;
+pub use newsletter_email_template::NewsletterEmailTemplate;
+pub use newsletter_issue_template::NewsletterIssueTemplate;
+pub use newsletter_issues_template::{NewsletterIssueSummary, NewsletterIssuesTemplate};
+pub use unsubscribe_template::UnsubscribeTemplate;
@@ -0,0 +1,9 @@
+use askama_actix::Template;
+
+// Layout of the issues authored in markdown, the content is already rendered to html
+#[derive(Template)]
+#[template(path = "newsletter_email.html")]
+pub struct NewsletterEmailTemplate<'a> {
+    pub title: &'a str,
+    pub html_content: &'a str,
+}
@@ -0,0 +1,10 @@
+use askama_actix::Template;
+
+// The issue html is written by the publishers, so it is rendered without escaping
+#[derive(Template)]
+#[template(path = "newsletter_issue.html")]
+pub struct NewsletterIssueTemplate<'a> {
+    pub title: &'a str,
+    pub published_at: &'a str,
+    pub html_content: &'a str,
+}
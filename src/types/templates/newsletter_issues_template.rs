@@ -0,0 +1,13 @@
+use askama_actix::Template;
+
+pub struct NewsletterIssueSummary {
+    pub newsletter_issue_id: String,
+    pub title: String,
+    pub published_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_issues.html")]
+pub struct NewsletterIssuesTemplate {
+    pub issues: Vec<NewsletterIssueSummary>,
+}
@@ -0,0 +1,8 @@
+use askama_actix::Template;
+
+// Without a link, the page confirms that the subscriber has already left
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+pub struct UnsubscribeTemplate<'a> {
+    pub unsubscribe_link: Option<&'a str>,
+}
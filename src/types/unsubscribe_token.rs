@@ -0,0 +1,46 @@
+use crate::routes::UnsubscribeParameters;
+
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn parse(token: String) -> Result<UnsubscribeToken, String> {
+        if (token.len() != 25) || !token.chars().all(|c| c.is_alphanumeric()) {
+            return Err(format!("{} is not a valid token!", token));
+        }
+
+        Ok(UnsubscribeToken(token))
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryInto<UnsubscribeToken> for UnsubscribeParameters {
+    type Error = String;
+
+    fn try_into(self) -> Result<UnsubscribeToken, Self::Error> {
+        UnsubscribeToken::parse(self.unsubscribe_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn test_unsubscribe_token_length_25() {
+        assert_ok!(UnsubscribeToken::parse("a".repeat(25)));
+        assert_err!(UnsubscribeToken::parse("b".repeat(24)));
+    }
+
+    #[test]
+    fn test_unsubscribe_token_with_non_alphanumeric_characters_invalid() {
+        assert_err!(UnsubscribeToken::parse("-".repeat(25)));
+    }
+}
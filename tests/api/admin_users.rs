@@ -0,0 +1,150 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_users().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn test_created_users_can_log_in() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": &password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let users: serde_json::Value = app.get_users().await.json().await.unwrap();
+    let usernames: Vec<_> = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["username"].as_str().unwrap())
+        .collect();
+    assert!(usernames.contains(&"new-publisher"));
+
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "new-publisher",
+            "password": &password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn test_duplicated_username_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[actix_web::test]
+async fn test_create_user_with_invalid_data_returns_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let cases = vec![
+        (
+            serde_json::json!({"username": "", "password": Uuid::new_v4().to_string()}),
+            "empty username",
+        ),
+        (
+            serde_json::json!({"username": "new-publisher", "password": "short"}),
+            "password too short",
+        ),
+        (
+            serde_json::json!({
+                "username": "new-publisher",
+                "password": Uuid::new_v4().to_string(),
+                "email": "not-an-email"
+            }),
+            "invalid email",
+        ),
+    ];
+
+    for (case, error) in cases {
+        let response = app.post_create_user(&case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with HTTP400 when the payload had an {}",
+            error
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_disabled_users_cannot_publish() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let password = Uuid::new_v4().to_string();
+
+    let user: serde_json::Value = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": &password,
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let response = app
+        .post_disable_user(user["user_id"].as_str().unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.web_address))
+        .basic_auth("new-publisher", Some(&password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_you_cannot_disable_yourself() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_disable_user(&app.test_user.user_id.to_string())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,194 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestingApp};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+// Returns the created token and its id
+async fn create_api_token(app: &TestingApp, body: serde_json::Value) -> (String, String) {
+    let response = app.post_create_api_token(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    (
+        body["token"].as_str().unwrap().to_string(),
+        body["api_token_id"].as_str().unwrap().to_string(),
+    )
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_api_tokens() {
+    let app = spawn_app().await;
+
+    let response = app.get_api_tokens().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_create_api_token(&serde_json::json!({ "name": "ci" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn test_api_tokens_can_publish_newsletters() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (token, _) = create_api_token(&app, serde_json::json!({ "name": "ci" })).await;
+
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), &token)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_api_tokens_are_only_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (token, api_token_id) = create_api_token(&app, serde_json::json!({ "name": "ci" })).await;
+    app.send_newsletter_with_token(newsletter_body(), &token)
+        .await;
+
+    let tokens: serde_json::Value = app.get_api_tokens().await.json().await.unwrap();
+    let tokens = tokens.as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["api_token_id"], api_token_id.as_str());
+    assert_eq!(tokens[0]["scopes"], serde_json::json!(["publish"]));
+    assert!(tokens[0].get("token").is_none());
+    assert!(!tokens[0]["last_used_at"].is_null());
+
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+}
+
+#[actix_web::test]
+async fn test_unknown_api_tokens_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), "nlt_not-a-real-token")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_revoked_api_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (token, api_token_id) = create_api_token(&app, serde_json::json!({ "name": "ci" })).await;
+
+    let response = app.delete_api_token(&api_token_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), &token)
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_revoking_an_unknown_api_token_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.delete_api_token(&Uuid::new_v4().to_string()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_expired_api_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (token, api_token_id) = create_api_token(
+        &app,
+        serde_json::json!({
+            "name": "ci",
+            "expires_at": Utc::now() + Duration::hours(1),
+        }),
+    )
+    .await;
+
+    sqlx::query!(
+        "UPDATE api_tokens SET expires_at = now() - interval '1 minute' WHERE api_token_id = $1",
+        Uuid::parse_str(&api_token_id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), &token)
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_api_tokens_are_limited_to_their_scopes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let (token, _) = create_api_token(
+        &app,
+        serde_json::json!({ "name": "dashboard", "scopes": ["read"] }),
+    )
+    .await;
+
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), &token)
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/newsletters/scheduled", &app.web_address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_create_api_token_returns_a_400_for_invalid_data() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (serde_json::json!({ "name": " " }), "empty name"),
+        (
+            serde_json::json!({ "name": "ci", "scopes": [] }),
+            "no scopes",
+        ),
+        (
+            serde_json::json!({ "name": "ci", "scopes": ["admin"] }),
+            "unknown scope",
+        ),
+        (
+            serde_json::json!({ "name": "ci", "expires_at": Utc::now() - Duration::hours(1) }),
+            "expiry in the past",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_create_api_token(&body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[actix_web::test]
 async fn test_requests_without_token_are_rejected() {
@@ -75,10 +75,16 @@ async fn test_confirmation_link_changes_database_status() {
     let response = reqwest::get(confirmation_link.text_link).await.unwrap();
     assert_eq!(response.status().as_u16(), 200);
 
-    let db_data = sqlx::query!("SELECT name, email, status FROM subscriptions",)
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch subscriber from the database");
+    let db_data = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, m.status
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch subscriber from the database");
 
     assert_eq!(db_data.email, "ursula_le_guin@gmail.com");
     assert_eq!(db_data.name, "le guin");
@@ -115,3 +121,24 @@ async fn test_already_confirmed_subscriptor_works() {
 
     assert_eq!(response.status().as_u16(), 200);
 }
+
+#[actix_web::test]
+async fn test_expired_confirmation_link_is_rejected() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link.text_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "pending_confirmation");
+}
@@ -0,0 +1,194 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestingApp};
+
+const USER_AGENT: &str = "Mozilla/5.0 (consent test)";
+const CLIENT_IP: &str = "203.0.113.7";
+
+// Goes through subscribe, confirm and unsubscribe as a browser behind a proxy would
+async fn subscribe_confirm_and_leave(app: &TestingApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .unwrap();
+
+    client
+        .post(format!("{}/subscriptions", app.web_address))
+        .header("X-Forwarded-For", CLIENT_IP)
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "homepage_footer"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_link(email_request).html_link;
+    client
+        .get(confirmation_link)
+        .header("X-Forwarded-For", CLIENT_IP)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let subscriber = sqlx::query!("SELECT id, unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, subscriber.unsubscribe_token
+        ))
+        .header("X-Forwarded-For", CLIENT_IP)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    subscriber.id.to_string()
+}
+
+#[actix_web::test]
+async fn test_consent_log_records_subscribe_confirm_and_unsubscribe() {
+    let app = spawn_app_with(|config| config.rate_limit.behind_proxy = true).await;
+    let subscriber_id = subscribe_confirm_and_leave(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_subscriber_consent(&subscriber_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["email"], "ursula_le_guin@gmail.com");
+    let events = export["events"].as_array().unwrap();
+    let event_types: Vec<_> = events.iter().map(|e| e["event_type"].clone()).collect();
+    assert_eq!(event_types, ["subscribe", "confirm", "unsubscribe"]);
+    for event in events {
+        assert_eq!(event["ip_address"], CLIENT_IP);
+        assert_eq!(event["user_agent"], USER_AGENT);
+        assert_eq!(event["list"], "default");
+        assert!(event["token"].is_string());
+        assert!(event["occurred_at"].is_string());
+    }
+    assert_eq!(events[0]["source"], "homepage_footer");
+    assert_eq!(events[1]["source"], "confirmation_link");
+    assert_eq!(events[2]["source"], "unsubscribe_link");
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    assert_eq!(events[0]["token"], subscription_token.as_str());
+    assert_eq!(events[1]["token"], subscription_token.as_str());
+}
+
+#[actix_web::test]
+async fn test_forwarded_addresses_are_only_recorded_behind_a_proxy() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe_confirm_and_leave(&app).await;
+    app.test_user.login(&app).await;
+
+    let export: serde_json::Value = app
+        .get_subscriber_consent(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    for event in export["events"].as_array().unwrap() {
+        assert_eq!(event["ip_address"], "127.0.0.1");
+    }
+}
+
+#[actix_web::test]
+async fn test_unsubscribing_twice_is_recorded_once() {
+    let app = spawn_app().await;
+    let subscriber_id = subscribe_confirm_and_leave(&app).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.test_user.login(&app).await;
+    let export: serde_json::Value = app
+        .get_subscriber_consent(&subscriber_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["events"].as_array().unwrap().len(), 3);
+}
+
+#[actix_web::test]
+async fn test_consent_log_cannot_be_changed() {
+    let app = spawn_app().await;
+    subscribe_confirm_and_leave(&app).await;
+
+    let update = sqlx::query!("UPDATE consent_events SET ip_address = '127.0.0.1'")
+        .execute(&app.db_pool)
+        .await;
+    assert!(update.is_err());
+
+    let delete = sqlx::query!("DELETE FROM consent_events")
+        .execute(&app.db_pool)
+        .await;
+    assert!(delete.is_err());
+}
+
+#[actix_web::test]
+async fn test_overly_long_form_sources_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.web_address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", &"a".repeat(101)),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_consent_export_requires_login_and_a_known_subscriber() {
+    let app = spawn_app().await;
+    let unknown_id = Uuid::new_v4().to_string();
+
+    let response = app.get_subscriber_consent(&unknown_id).await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.test_user.login(&app).await;
+    let response = app.get_subscriber_consent(&unknown_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
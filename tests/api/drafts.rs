@@ -0,0 +1,282 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, BatchDelivered, TestingApp,
+};
+
+fn draft_body(title: &str, markdown: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "content": {"markdown": markdown}
+    })
+}
+
+// Creates a draft as the logged in user, returning its id
+async fn create_draft(app: &TestingApp, title: &str, markdown: &str) -> String {
+    let response = app.post_create_draft(&draft_body(title, markdown)).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    draft["draft_id"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let response = app.get_drafts().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_create_draft(&draft_body("Title", "Some *markdown*"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_publish_draft(&uuid::Uuid::new_v4().to_string())
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn test_updating_a_draft_keeps_every_revision() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "First title", "First body").await;
+
+    let response = app
+        .put_update_draft(&draft_id, &draft_body("Second title", "Second body"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let draft: serde_json::Value = app.get_draft(&draft_id).await.json().await.unwrap();
+    assert_eq!(draft["revision"], 2);
+    assert_eq!(draft["content"]["title"], "Second title");
+
+    let revisions: serde_json::Value = app
+        .get_draft_revisions(&draft_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let revisions = revisions.as_array().unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert!(revisions
+        .iter()
+        .all(|r| r["saved_by"] == app.test_user.username.as_str()));
+
+    let first: serde_json::Value = app
+        .get_draft_revision(&draft_id, 1)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(first["content"]["title"], "First title");
+    assert_eq!(first["content"]["content"]["markdown"], "First body");
+
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert_eq!(drafts[0]["title"], "Second title");
+    assert_eq!(drafts[0]["revision"], 2);
+}
+
+#[actix_web::test]
+async fn test_revisions_can_be_diffed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Title", "Unchanged line\nOld line").await;
+    app.put_update_draft(&draft_id, &draft_body("Title", "Unchanged line\nNew line"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app.get_draft_diff(&draft_id, 1, 2).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let diff = response.text().await.unwrap();
+    assert!(diff.contains("--- revision 1"));
+    assert!(diff.contains("+++ revision 2"));
+    assert!(diff.contains("-Old line"));
+    assert!(diff.contains("+New line"));
+    assert!(diff.contains(" Unchanged line"));
+    assert!(!diff.contains("-Title"));
+}
+
+#[actix_web::test]
+async fn test_unknown_drafts_and_revisions_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let unknown_id = uuid::Uuid::new_v4().to_string();
+
+    assert_eq!(app.get_draft(&unknown_id).await.status().as_u16(), 404);
+    assert_eq!(app.delete_draft(&unknown_id).await.status().as_u16(), 404);
+    assert_eq!(
+        app.put_update_draft(&unknown_id, &draft_body("Title", "Body"))
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        app.post_publish_draft(&unknown_id).await.status().as_u16(),
+        404
+    );
+
+    let draft_id = create_draft(&app, "Title", "Body").await;
+    assert_eq!(
+        app.get_draft_revision(&draft_id, 2).await.status().as_u16(),
+        404
+    );
+    assert_eq!(
+        app.get_draft_diff(&draft_id, 1, 2).await.status().as_u16(),
+        404
+    );
+}
+
+#[actix_web::test]
+async fn test_deleted_drafts_are_gone() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Title", "Body").await;
+
+    let response = app.delete_draft(&draft_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_draft(&draft_id).await.status().as_u16(), 404);
+    let drafts: serde_json::Value = app.get_drafts().await.json().await.unwrap();
+    assert!(drafts.as_array().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_publishing_a_draft_delivers_its_latest_revision() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Old title", "Old body").await;
+    app.put_update_draft(&draft_id, &draft_body("New title", "New **body**"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_publish_draft(&draft_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+    assert_eq!(body["Subject"], "New title");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("New <strong>body</strong>"));
+
+    let draft: serde_json::Value = app.get_draft(&draft_id).await.json().await.unwrap();
+    assert!(!draft["published_at"].is_null());
+}
+
+#[actix_web::test]
+async fn test_published_drafts_cannot_be_changed_or_published_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Title", "Body").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_draft(&draft_id)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        app.post_publish_draft(&draft_id).await.status().as_u16(),
+        409
+    );
+    assert_eq!(
+        app.put_update_draft(&draft_id, &draft_body("Title", "Edited"))
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+    assert_eq!(app.delete_draft(&draft_id).await.status().as_u16(), 409);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_retrying_a_draft_publication_returns_the_saved_response() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Title", "Body").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = app
+            .post_publish_draft_with_idempotency_key(&draft_id, "publish-draft")
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_reused_idempotency_keys_leave_other_drafts_unpublished() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let published_id = create_draft(&app, "Published", "Body").await;
+    let other_id = create_draft(&app, "Other", "Body").await;
+
+    app.post_publish_draft_with_idempotency_key(&published_id, "publish-draft")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_publish_draft_with_idempotency_key(&other_id, "publish-draft")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let draft: serde_json::Value = app.get_draft(&other_id).await.json().await.unwrap();
+    assert!(draft["published_at"].is_null());
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[actix_web::test]
+async fn test_drafts_that_fail_validation_stay_unpublished() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app, "Title", "Hi {{ surname }}").await;
+
+    let response = app.post_publish_draft(&draft_id).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let draft: serde_json::Value = app.get_draft(&draft_id).await.json().await.unwrap();
+    assert!(draft["published_at"].is_null());
+    app.put_update_draft(&draft_id, &draft_body("Title", "Hi {{ name }}"))
+        .await
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,270 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestingApp,
+};
+
+const SUBSCRIBER_EMAIL: &str = "ursula_le_guin@gmail.com";
+
+fn bounce_event(bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807_i64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Email": SUBSCRIBER_EMAIL,
+        "BouncedAt": "2024-07-22T10:00:00Z",
+    })
+}
+
+async fn subscriber_statuses(app: &TestingApp) -> Vec<String> {
+    sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_email_events_require_webhook_credentials() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", app.web_address))
+        .json(&bounce_event("HardBounce"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", app.web_address))
+        .basic_auth(&app.webhook.username, Some("wrong-password"))
+        .json(&bounce_event("HardBounce"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_hard_bounces_stop_deliveries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_event(&bounce_event("HardBounce")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_statuses(&app).await, vec!["bounced"]);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.send_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_bounced_addresses_are_not_confirmed_again_on_resubscription() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_event(&bounce_event("HardBounce")).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .send_subscription_request("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_statuses(&app).await, vec!["bounced"]);
+}
+
+#[actix_web::test]
+async fn test_bounced_addresses_cannot_join_other_lists() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_event(&bounce_event("HardBounce")).await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": "rust-weekly", "name": "Rust weekly" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .send_subscription_request(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list=rust-weekly".into(),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_resend_confirmation(SUBSCRIBER_EMAIL).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(subscriber_statuses(&app).await, vec!["bounced"]);
+}
+
+#[actix_web::test]
+async fn test_old_confirmation_links_do_not_bring_bounced_addresses_back() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_link.html_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.post_email_event(&bounce_event("HardBounce")).await;
+
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+
+    assert!(response.status().is_client_error());
+    assert_eq!(subscriber_statuses(&app).await, vec!["bounced"]);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.send_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_unsubscribing_keeps_addresses_suppressed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_email_event(&bounce_event("HardBounce")).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, unsubscribe_token
+        ))
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_statuses(&app).await, vec!["bounced"]);
+}
+
+#[actix_web::test]
+async fn test_spam_complaints_are_recorded() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_email_event(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "Email": SUBSCRIBER_EMAIL,
+            "BouncedAt": "2024-07-22T10:00:00Z",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_statuses(&app).await, vec!["complained"]);
+}
+
+#[actix_web::test]
+async fn test_soft_bounces_and_deliveries_are_only_audited() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.post_email_event(&bounce_event("SoftBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_email_event(&serde_json::json!({
+        "RecordType": "Delivery",
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Recipient": SUBSCRIBER_EMAIL,
+        "DeliveredAt": "2024-07-22T10:00:00Z",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(subscriber_statuses(&app).await, vec!["confirmed"]);
+    let events =
+        sqlx::query!("SELECT record_type, email, payload FROM email_events ORDER BY received_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].record_type, "bounce");
+    assert_eq!(events[0].payload["Type"], "SoftBounce");
+    assert_eq!(events[1].record_type, "delivery");
+    assert_eq!(events[1].email, SUBSCRIBER_EMAIL);
+}
+
+#[actix_web::test]
+async fn test_unsupported_event_types_are_ignored() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_email_event(&serde_json::json!({"RecordType": "Open", "Recipient": SUBSCRIBER_EMAIL}))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_malformed_events_are_rejected() {
+    let app = spawn_app().await;
+    let cases = vec![
+        (
+            serde_json::json!({"Email": SUBSCRIBER_EMAIL}),
+            "missing record type",
+        ),
+        (
+            serde_json::json!({"RecordType": "Bounce"}),
+            "missing bounce fields",
+        ),
+        (
+            serde_json::json!({"RecordType": "Delivery"}),
+            "missing recipient",
+        ),
+    ];
+
+    for (body, description) in cases {
+        let response = app.post_email_event(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail when the payload was invalid: {}",
+            description
+        );
+    }
+}
@@ -1,11 +1,18 @@
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
 
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, RateLimit, Settings, WebhookSettings},
+    email_client::EmailClient,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    newsletter_scheduler::publish_due_issues,
     startup::Application,
     telemetry,
 };
@@ -24,6 +31,10 @@ pub struct TestingApp {
     pub email_server: MockServer,
     pub port: u16,
     pub test_user: TestUser,
+    pub email_client: EmailClient,
+    pub api_client: reqwest::Client,
+    pub base_url: String,
+    pub webhook: WebhookSettings,
 }
 
 #[derive(Debug)]
@@ -35,7 +46,7 @@ pub struct ConfirmationLink {
 impl TestingApp {
     pub async fn send_subscription_request(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.web_address))
+            .post(format!("{}/subscriptions", &self.web_address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -43,10 +54,528 @@ impl TestingApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/subscriptions/resend-confirmation",
+                &self.web_address
+            ))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn send_newsletter(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/newsletters", &self.web_address))
+            .post(format!("{}/newsletters", &self.web_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn send_newsletter_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.web_address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_preview_newsletter(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters/preview", &self.web_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_test_send_newsletter(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters/test-send", &self.web_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_scheduled_newsletters(&self) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/newsletters/scheduled", &self.web_address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_newsletter_stats(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/newsletters/{}/stats",
+                &self.web_address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_reschedule_newsletter(
+        &self,
+        newsletter_issue_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/scheduled/{}/reschedule",
+                &self.web_address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_cancel_newsletter(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/newsletters/scheduled/{}/cancel",
+                &self.web_address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Makes every scheduled issue due and runs the scheduler once
+    pub async fn publish_scheduled_newsletters_now(&self) {
+        sqlx::query!("UPDATE newsletter_issues SET send_at = now() WHERE status = 'scheduled'")
+            .execute(&self.db_pool)
+            .await
+            .unwrap();
+        publish_due_issues(&self.db_pool).await.unwrap();
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, &self.email_client, &self.base_url)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    // Every email that went out in a batch request, in the order they were sent
+    pub async fn delivered_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/email/batch")
+            .flat_map(|request| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&request.body).unwrap()
+            })
+            .collect()
+    }
+
+    pub async fn post_email_event(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email-events", &self.web_address))
+            .basic_auth(
+                &self.webhook.username,
+                Some(self.webhook.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.web_address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.web_address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_create_list(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.web_address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_consent(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}/consent",
+                &self.web_address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_tag_subscriber(&self, subscriber_id: &str, tag: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.web_address, subscriber_id
+            ))
+            .json(&serde_json::json!({ "tag": tag }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_subscriber_tag(&self, subscriber_id: &str, tag: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/admin/subscribers/{}/tags/{}",
+                &self.web_address, subscriber_id, tag
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_create_draft(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/drafts", &self.web_address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn put_update_draft(
+        &self,
+        draft_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/admin/drafts/{}", &self.web_address, draft_id))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/drafts/{}", &self.web_address, draft_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_drafts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/drafts", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_draft(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/admin/drafts/{}", &self.web_address, draft_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft_revisions(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/drafts/{}/revisions",
+                &self.web_address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft_revision(&self, draft_id: &str, revision: i32) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/drafts/{}/revisions/{}",
+                &self.web_address, draft_id, revision
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft_diff(&self, draft_id: &str, from: i32, to: i32) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/drafts/{}/diff?from={}&to={}",
+                &self.web_address, draft_id, from, to
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_publish_draft(&self, draft_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                &self.web_address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_publish_draft_with_idempotency_key(
+        &self,
+        draft_id: &str,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                &self.web_address, draft_id
+            ))
+            .header("Idempotency-Key", idempotency_key)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_personal_data(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/personal-data", &self.web_address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_personal_data(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/admin/personal-data", &self.web_address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_create_user(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users", &self.web_address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_disable_user(&self, user_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/disable",
+                &self.web_address, user_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn put_user_role(&self, user_id: &str, role: &str) -> reqwest::Response {
+        self.api_client
+            .put(format!(
+                "{}/admin/users/{}/role",
+                &self.web_address, user_id
+            ))
+            .json(&serde_json::json!({ "role": role }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_two_factor_status(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_start_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_confirm_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor/confirm", &self.web_address))
+            .json(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/admin/two-factor", &self.web_address))
+            .json(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_unlock_user(&self, user_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/unlock",
+                &self.web_address, user_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_create_api_token(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.web_address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_api_tokens(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.web_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_api_token(&self, api_token_id: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/admin/api-tokens/{}",
+                &self.web_address, api_token_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn send_newsletter_with_token(
+        &self,
+        body: serde_json::Value,
+        token: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.web_address))
+            .bearer_auth(token)
             .json(&body)
             .send()
             .await
@@ -87,6 +616,11 @@ impl TestingApp {
 }
 
 pub async fn spawn_app() -> TestingApp {
+    spawn_app_with(|_| {}).await
+}
+
+// Lets a test adjust the configuration the application is built with
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestingApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -97,6 +631,16 @@ pub async fn spawn_app() -> TestingApp {
         config.database.name = Uuid::new_v4().to_string();
         config.application.port = 0;
         config.email_client.base_url = email_server.uri();
+        config.email_client.test_recipients = vec!["editor@example.com".into()];
+        // Out of the way of tests that are not about rate limiting
+        let generous_limit = RateLimit {
+            max_requests: 1000,
+            window_seconds: 3600,
+        };
+        config.rate_limit.per_ip = generous_limit;
+        config.rate_limit.per_email = generous_limit;
+        config.rate_limit.per_domain = generous_limit;
+        customize(&mut config);
 
         config
     };
@@ -109,7 +653,7 @@ pub async fn spawn_app() -> TestingApp {
     let web_address = format!("http://127.0.0.1:{}", application.get_application_port());
     let port = application.get_application_port();
 
-    let _ = tokio::spawn(application.run_application());
+    tokio::spawn(application.run_application());
 
     let testing_app = TestingApp {
         web_address,
@@ -117,6 +661,14 @@ pub async fn spawn_app() -> TestingApp {
         email_server,
         port,
         test_user: TestUser::generate(),
+        webhook: configuration.email_client.webhook.clone(),
+        email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url,
+        api_client: reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap(),
     };
 
     testing_app
@@ -127,6 +679,97 @@ pub async fn spawn_app() -> TestingApp {
     testing_app
 }
 
+pub async fn create_unconfirmed_subscriber(app: &TestingApp) -> ConfirmationLink {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.send_subscription_request(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_server_response = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_link(email_server_response)
+}
+
+// Subscribes and confirms `email`, returning its subscriber id
+pub async fn create_confirmed_subscriber_with_email(app: &TestingApp, email: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+    app.send_subscription_request(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let confirmation_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_link(&confirmation_request).html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+        .to_string()
+}
+
+pub async fn create_confirmed_subscriber(app: &TestingApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+
+    reqwest::get(confirmation_link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+// Mocks a Postmark batch request that accepts every one of its emails
+pub struct BatchDelivered;
+
+impl Respond for BatchDelivered {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<_> = emails
+            .iter()
+            .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
+
 async fn configure_testing_database(config: &DatabaseSettings) -> PgPool {
     // Connect to postgres, not to a specific postgres database
     let mut db_connection = PgConnection::connect_with(&config.connect_database_engine())
@@ -155,6 +798,7 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub email: String,
 }
 
 impl TestUser {
@@ -163,9 +807,18 @@ impl TestUser {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            email: "publisher@example.com".into(),
         }
     }
 
+    pub async fn login(&self, app: &TestingApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password
+        }))
+        .await;
+    }
+
     async fn store_in_db(&self, db_pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
@@ -177,9 +830,11 @@ impl TestUser {
         .unwrap()
         .to_string();
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, email, role, password_hash) \
+            VALUES ($1, $2, $3, 'admin', $4)",
             self.user_id,
             self.username,
+            self.email,
             password_hash
         )
         .execute(db_pool)
@@ -0,0 +1,195 @@
+use reqwest::header::RETRY_AFTER;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestingApp};
+
+// A client of its own, so that logging in does not touch the session of the app's client
+async fn login_as(app: &TestingApp, username: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(format!("{}/login", &app.web_address))
+        .form(&serde_json::json!({
+            "username": username,
+            "password": password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+fn retry_after(response: &reqwest::Response) -> u64 {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .expect("Missing Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_repeated_failed_logins_lock_the_account_out() {
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+
+    for _ in 0..3 {
+        let response = login_as(&app, username, "wrong-password").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right password is turned away while locked out
+    let response = login_as(&app, username, &app.test_user.password).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let wait = retry_after(&response);
+    assert!(wait > 0 && wait <= 30);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Too many failed login attempts"));
+}
+
+#[actix_web::test]
+async fn test_concurrent_guesses_cannot_get_past_the_lockout() {
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+
+    let responses = tokio::join!(
+        login_as(&app, username, "wrong-password-1"),
+        login_as(&app, username, "wrong-password-2"),
+        login_as(&app, username, "wrong-password-3"),
+        login_as(&app, username, "wrong-password-4"),
+        login_as(&app, username, "wrong-password-5"),
+        login_as(&app, username, "wrong-password-6"),
+    );
+    let statuses = [
+        responses.0.status(),
+        responses.1.status(),
+        responses.2.status(),
+        responses.3.status(),
+        responses.4.status(),
+        responses.5.status(),
+    ];
+
+    // Only the guesses that were actually checked are answered with 401
+    let n_checked = statuses.iter().filter(|s| s.as_u16() == 401).count();
+    assert!(n_checked <= 3);
+    assert!(statuses
+        .iter()
+        .all(|s| s.as_u16() == 401 || s.as_u16() == 429));
+    let failures = sqlx::query!(
+        "SELECT failures FROM failed_login_attempts WHERE scope = 'username' AND subject = $1",
+        username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .failures;
+    assert_eq!(failures as usize, n_checked);
+}
+
+#[actix_web::test]
+async fn test_a_successful_login_forgets_previous_failures() {
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+
+    for _ in 0..2 {
+        for _ in 0..2 {
+            let response = login_as(&app, username, "wrong-password").await;
+            assert_eq!(response.status().as_u16(), 401);
+        }
+        let response = login_as(&app, username, &app.test_user.password).await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+}
+
+#[actix_web::test]
+async fn test_repeated_failures_from_one_ip_lock_out_every_username() {
+    let app = spawn_app().await;
+
+    for _ in 0..10 {
+        let response = login_as(&app, &Uuid::new_v4().to_string(), "wrong-password").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = login_as(&app, &app.test_user.username, &app.test_user.password).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[actix_web::test]
+async fn test_publishing_with_credentials_is_locked_out_as_well() {
+    let app = spawn_app().await;
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    for _ in 0..3 {
+        let response = reqwest::Client::new()
+            .post(format!("{}/newsletters", &app.web_address))
+            .basic_auth(&app.test_user.username, Some("wrong-password"))
+            .json(&newsletter_body)
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(retry_after(&response) > 0);
+}
+
+#[actix_web::test]
+async fn test_admins_can_unlock_a_locked_out_account() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let password = Uuid::new_v4().to_string();
+    let user: serde_json::Value = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": &password,
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let user_id = user["user_id"].as_str().unwrap();
+
+    for _ in 0..3 {
+        login_as(&app, "new-publisher", "wrong-password").await;
+    }
+    let response = login_as(&app, "new-publisher", &password).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let users: serde_json::Value = app.get_users().await.json().await.unwrap();
+    let locked_user = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|u| u["user_id"] == user_id)
+        .unwrap();
+    assert!(!locked_user["locked_until"].is_null());
+
+    let response = app.post_unlock_user(user_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login_as(&app, "new-publisher", &password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn test_unlocking_an_unknown_user_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_unlock_user(&Uuid::new_v4().to_string()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,181 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, BatchDelivered, TestingApp,
+};
+
+const SUBSCRIBER_BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn create_list(app: &TestingApp, slug: &str) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": slug, "name": "Rust weekly" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn publish_to_list(app: &TestingApp, slug: &str) {
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "list": slug,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app.get_lists().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": "rust-weekly", "name": "Rust weekly" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn test_created_lists_are_listed_and_slugs_are_unique() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+
+    let lists: serde_json::Value = app.get_lists().await.json().await.unwrap();
+    let slugs: Vec<_> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, vec!["default", "rust-weekly"]);
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": "rust-weekly", "name": "Again" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[actix_web::test]
+async fn test_invalid_list_slug_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": "Rust Weekly", "name": "Rust weekly" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let body = format!("{}&list=does-not-exist", SUBSCRIBER_BODY);
+    let response = app.send_subscription_request(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_each_list_has_its_own_confirmation_status() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .mount(&app.email_server)
+        .await;
+    let body = format!("{}&list=rust-weekly", SUBSCRIBER_BODY);
+    let response = app.send_subscription_request(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let confirmation_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_link(&confirmation_request);
+
+    // Pending on the new list, so only the issue of the default list is delivered
+    publish_to_list(&app, "rust-weekly").await;
+    publish_to_list(&app, "default").await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(app.delivered_emails().await.len(), 1);
+
+    reqwest::get(confirmation_link.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    publish_to_list(&app, "rust-weekly").await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(app.delivered_emails().await.len(), 2);
+}
+
+#[actix_web::test]
+async fn test_unsubscribing_from_a_list_keeps_the_other_memberships() {
+    let app = spawn_app().await;
+    create_list(&app, "rust-weekly").await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at)
+        SELECT s.id, l.list_id, 'confirmed', now()
+        FROM subscriptions s, lists l
+        WHERE l.slug = 'rust-weekly'
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}&list=rust-weekly",
+            app.web_address, unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(memberships[0].slug, "default");
+    assert_eq!(memberships[0].status, "confirmed");
+    assert_eq!(memberships[1].slug, "rust-weekly");
+    assert_eq!(memberships[1].status, "unsubscribed");
+}
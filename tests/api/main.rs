@@ -1,5 +1,26 @@
+mod admin_dashboard;
+mod admin_users;
+mod api_tokens;
+mod change_password;
 mod confirm_subscriptions;
+mod consent;
+mod drafts;
+mod email_events;
 mod health_check;
 mod helpers;
+mod login;
+mod login_lockout;
+mod mailing_lists;
 mod newsletter;
+mod newsletter_issues;
+mod newsletter_previews;
+mod personal_data;
+mod rate_limiting;
+mod resend_confirmation;
+mod roles;
+mod scheduled_newsletters;
+mod segments;
 mod subscriptions;
+mod tracking;
+mod two_factor;
+mod unsubscribe;
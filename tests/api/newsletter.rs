@@ -4,14 +4,17 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{spawn_app, ConfirmationLink, TestingApp};
+use crate::helpers::{
+    create_confirmed_subscriber, create_confirmed_subscriber_with_email,
+    create_unconfirmed_subscriber, spawn_app, BatchDelivered,
+};
 
 #[actix_web::test]
 async fn test_requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.web_address))
+        .post(format!("{}/newsletters", &app.web_address))
         .json(&serde_json::json!({
             "title": "Newsletter title",
             "content": {
@@ -37,7 +40,7 @@ async fn test_mails_are_delivered_to_confirmed_subscribers() {
 
     // Mock Postmark and assert no requests are fired
     Mock::given(any())
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(BatchDelivered)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -52,6 +55,156 @@ async fn test_mails_are_delivered_to_confirmed_subscribers() {
     });
     let response = app.send_newsletter(newsletter_body).await;
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_recipients_are_delivered_in_a_single_batch() {
+    let app = spawn_app().await;
+    let recipients = [
+        "first@example.com",
+        "second@example.com",
+        "third@example.com",
+    ];
+    for email in recipients {
+        create_confirmed_subscriber_with_email(&app, email).await;
+    }
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let mut delivered: Vec<_> = app
+        .delivered_emails()
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_string())
+        .collect();
+    delivered.sort();
+    let mut expected = recipients.map(String::from).to_vec();
+    expected.sort();
+    assert_eq!(delivered, expected);
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+#[actix_web::test]
+async fn test_rejected_messages_of_a_batch_are_retried_on_their_own() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "accepted@example.com").await;
+    create_confirmed_subscriber_with_email(&app, "rejected@example.com").await;
+
+    // Postmark rejects one message of the batch and accepts the other
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &wiremock::Request| {
+            let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = emails
+                .iter()
+                .map(|email| {
+                    if email["To"] == "rejected@example.com" {
+                        serde_json::json!({ "ErrorCode": 406, "Message": "Inactive recipient" })
+                    } else {
+                        serde_json::json!({ "ErrorCode": 0, "Message": "OK" })
+                    }
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        })
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let tasks = sqlx::query!(
+        "SELECT subscriber_email, n_retries, execute_after > now() AS \"postponed!\" \
+        FROM issue_delivery_queue"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].subscriber_email, "rejected@example.com");
+    assert_eq!(tasks[0].n_retries, 1);
+    assert!(tasks[0].postponed);
+}
+
+#[actix_web::test]
+async fn test_tasks_that_cannot_be_prepared_do_not_block_the_batch() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .mount(&app.email_server)
+        .await;
+
+    for title in ["Broken issue", "Newsletter title"] {
+        let response = app
+            .send_newsletter(serde_json::json!({
+                "title": title,
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    // Stored templates are validated on publish, only a later change can break them
+    sqlx::query!(
+        "UPDATE newsletter_issues SET html_content = '{{ surname }}' WHERE title = 'Broken issue'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let delivered = app.delivered_emails().await;
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0]["Subject"], "Newsletter title");
+    let task = sqlx::query!(
+        "SELECT i.title, q.n_retries FROM issue_delivery_queue q \
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.title, "Broken issue");
+    assert_eq!(task.n_retries, 1);
 }
 
 #[actix_web::test]
@@ -76,6 +229,7 @@ async fn test_mails_are_not_delivered_to_unconfirmed_subscribers() {
     });
     let response = app.send_newsletter(newsletter_body).await;
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
 }
 
 #[actix_web::test]
@@ -97,6 +251,15 @@ async fn test_newsletter_with_invalid_data_returns_400() {
             }),
             "missing content",
         ),
+        (
+            serde_json::json!({
+                "title": "Newsletter",
+                "content": {
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing text body without markdown",
+        ),
     ];
 
     for (case, error) in cases {
@@ -119,7 +282,7 @@ async fn test_non_existing_user_is_rejected() {
     assert_ne!(app.test_user.username, username);
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.web_address))
+        .post(format!("{}/newsletters", &app.web_address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
                 "title": "Newsletter title",
@@ -148,7 +311,7 @@ async fn test_user_with_invalid_password_is_rejected() {
     assert_ne!(app.test_user.password, password);
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.web_address))
+        .post(format!("{}/newsletters", &app.web_address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
                 "title": "Newsletter title",
@@ -168,39 +331,346 @@ async fn test_user_with_invalid_password_is_rejected() {
     );
 }
 
-async fn create_unconfirmed_subscriber(app: &TestingApp) -> ConfirmationLink {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+#[actix_web::test]
+async fn test_publishing_does_not_wait_for_delivery() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Emails are only sent by the delivery worker, never inside the request
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 1);
+}
+
+#[actix_web::test]
+async fn test_failed_deliveries_are_retried_later() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    // The task is kept in the queue and postponed instead of being dropped
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"postponed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.postponed);
+}
+
+#[actix_web::test]
+async fn test_newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .respond_with(BatchDelivered)
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
 
-    app.send_subscription_request(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
 
-    let email_server_response = &app
-        .email_server
-        .received_requests()
-        .await
+    let response = app
+        .send_newsletter_with_idempotency_key(newsletter_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Retry the same request, it must be replayed instead of publishing the issue again
+    let response = app
+        .send_newsletter_with_idempotency_key(newsletter_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_concurrent_newsletter_submissions_are_serialized() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let first_response =
+        app.send_newsletter_with_idempotency_key(newsletter_body.clone(), &idempotency_key);
+    let second_response =
+        app.send_newsletter_with_idempotency_key(newsletter_body, &idempotency_key);
+    let (first_response, second_response) = tokio::join!(first_response, second_response);
+
+    assert_eq!(first_response.status(), second_response.status());
+    assert_eq!(
+        first_response.text().await.unwrap(),
+        second_response.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_invalid_idempotency_key_is_rejected() {
+    let app = spawn_app().await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let long_key = "a".repeat(51);
+    let cases = vec![("", "empty key"), (long_key.as_str(), "key too long")];
+
+    for (idempotency_key, error) in cases {
+        let response = app
+            .send_newsletter_with_idempotency_key(newsletter_body.clone(), idempotency_key)
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with HTTP400 for an idempotency key with {}",
+            error
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_markdown_content_renders_both_bodies() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "# Hello\n\nThis is **important**.",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.contains("<title>Newsletter title</title>"));
+    assert!(html.contains("<h1>Hello</h1>\n<p>This is <strong>important</strong>.</p>"));
+    assert!(body["TextBody"]
+        .as_str()
         .unwrap()
-        .pop()
-        .unwrap();
+        .starts_with("Hello\n\nThis is important."));
+}
+
+#[actix_web::test]
+async fn test_placeholders_in_markdown_links_are_filled_in() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    app.get_confirmation_link(email_server_response)
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "Hi {{ name }}, [leave]({{unsubscribe_url}}) any time.",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.contains(
+        "<p>Hi le guin, <a href=\"http://127.0.0.1/subscriptions/unsubscribe?unsubscribe_token="
+    ));
+    assert!(!html.contains("{{") && !html.contains("%7B"));
+    let text = body["TextBody"].as_str().unwrap();
+    assert!(text.starts_with(
+        "Hi le guin, leave (http://127.0.0.1/subscriptions/unsubscribe?unsubscribe_token="
+    ));
 }
 
-async fn create_confirmed_subscriber(app: &TestingApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+#[actix_web::test]
+async fn test_explicit_bodies_override_markdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
 
-    reqwest::get(confirmation_link.html_link)
-        .await
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "# Hello",
+                "text": "Hand written text body",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<h1>Hello</h1>"));
+    assert!(body["TextBody"]
+        .as_str()
         .unwrap()
-        .error_for_status()
-        .unwrap();
+        .starts_with("Hand written text body"));
+}
+
+#[actix_web::test]
+async fn test_issues_are_personalized_for_every_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "News for {{ name }}",
+            "content": {
+                "text": "Hi {{ name }}, this went to {{ email }}",
+                "html": "<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+    assert_eq!(body["Subject"], "News for le guin");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hi le guin, this went to ursula_le_guin@gmail.com"));
+    let html = body["HtmlBody"].as_str().unwrap();
+    assert!(html.starts_with(
+        "<p>Hi le guin</p><a href=\"http://127.0.0.1/subscriptions/unsubscribe?unsubscribe_token="
+    ));
+    assert!(!html.contains("{{"));
+}
+
+#[actix_web::test]
+async fn test_malformed_placeholders_are_rejected_at_publish_time() {
+    let app = spawn_app().await;
+    let cases = vec![
+        (
+            "Hi {{ name",
+            "<p>Body</p>",
+            "Body",
+            "unclosed placeholder in the title",
+        ),
+        (
+            "Title",
+            "<p>Hi {{ surname }}</p>",
+            "Body",
+            "unknown placeholder in the html",
+        ),
+        (
+            "Title",
+            "<p>Body</p>",
+            "Hi {{ }}",
+            "empty placeholder in the text",
+        ),
+    ];
+
+    for (title, html, text, error) in cases {
+        let response = app
+            .send_newsletter(serde_json::json!({
+                "title": title,
+                "content": {
+                    "text": text,
+                    "html": html,
+                }
+            }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with HTTP400 for a newsletter with an {}",
+            error
+        );
+    }
 }
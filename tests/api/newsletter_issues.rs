@@ -0,0 +1,111 @@
+use uuid::Uuid;
+
+use crate::helpers::spawn_app;
+
+#[actix_web::test]
+async fn test_published_issues_are_listed_in_the_archive() {
+    let app = spawn_app().await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Archived newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(format!("{}/newsletters", app.web_address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Archived newsletter title"));
+}
+
+#[actix_web::test]
+async fn test_published_issue_can_be_read_back() {
+    let app = spawn_app().await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id, published_by FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the published issue");
+    assert_eq!(issue.published_by, Some(app.test_user.user_id));
+
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}",
+        app.web_address, issue.newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[actix_web::test]
+async fn test_unknown_issue_returns_404() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}",
+        app.web_address,
+        Uuid::new_v4()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_segmented_issues_are_kept_out_of_the_archive() {
+    let app = spawn_app().await;
+
+    let newsletter_body = serde_json::json!({
+        "title": "Beta testers only",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "segment": "beta",
+    });
+    let response = app.send_newsletter(newsletter_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(format!("{}/newsletters", app.web_address))
+        .await
+        .unwrap();
+    assert!(!response.text().await.unwrap().contains("Beta testers only"));
+
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}",
+        app.web_address, newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,127 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+
+fn personalized_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "News for {{ name }}",
+        "content": {
+            "text": "Hi {{ name }}, this went to {{ email }}",
+            "markdown": "Hi **{{ name }}**",
+        }
+    })
+}
+
+#[actix_web::test]
+async fn test_preview_renders_the_issue_for_a_sample_subscriber() {
+    let app = spawn_app().await;
+
+    let response = app.post_preview_newsletter(&personalized_issue()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p>Hi <strong>Jane Doe</strong></p>"));
+    assert!(html.contains("Unsubscribe"));
+}
+
+#[actix_web::test]
+async fn test_preview_and_test_send_require_authentication() {
+    let app = spawn_app().await;
+
+    for endpoint in ["preview", "test-send"] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/newsletters/{}", app.web_address, endpoint))
+            .json(&personalized_issue())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[actix_web::test]
+async fn test_previewing_malformed_issues_fails() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Title",
+            "content": {"markdown": "Hi {{ surname }}"}
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_test_send_goes_to_the_publisher_only() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_test_send_newsletter(&personalized_issue()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], app.test_user.email);
+    assert_eq!(
+        body["Subject"],
+        format!("[Test] News for {}", app.test_user.username)
+    );
+    // Nothing is queued for the subscribers
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_test_send_to_allowed_recipients() {
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut issue = personalized_issue();
+    issue["recipient"] = "editor@example.com".into();
+    let response = app.post_test_send_newsletter(&issue).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_test_send_to_other_recipients_is_rejected() {
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut issue = personalized_issue();
+    issue["recipient"] = "someone-else@example.com".into();
+    let response = app.post_test_send_newsletter(&issue).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
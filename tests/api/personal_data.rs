@@ -0,0 +1,230 @@
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+use zero2prod::personal_data::tombstone_hash;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, spawn_app_with, BatchDelivered,
+    TestingApp,
+};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+// Confirmed subscriber that received an issue and opened it
+async fn create_subscriber_with_history(app: &TestingApp) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.send_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let open_token =
+        sqlx::query!("SELECT tracking_token FROM tracking_tokens WHERE link_id IS NULL")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .tracking_token;
+    reqwest::get(format!("{}/t/o/{}", app.web_address, open_token))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn get_unsubscribe_token(app: &TestingApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token
+}
+
+async fn count_rows(app: &TestingApp, table: &str) -> i64 {
+    sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_personal_data() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&app.get_personal_data(EMAIL).await, "/login");
+    assert_is_redirect_to(&app.delete_personal_data(EMAIL).await, "/login");
+}
+
+#[actix_web::test]
+async fn test_export_contains_everything_held_about_a_subscriber() {
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_personal_data(EMAIL).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+    assert_eq!(export["subscriber"]["name"], "le guin");
+    assert_eq!(export["memberships"][0]["status"], "confirmed");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["consent_events"].as_array().unwrap().len(), 2);
+    assert_eq!(export["deliveries"][0]["title"], "Newsletter title");
+    assert_eq!(export["tracking_events"].as_array().unwrap().len(), 1);
+    assert!(export["tracking_events"][0]["url"].is_null());
+}
+
+#[actix_web::test]
+async fn test_unknown_and_invalid_emails_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    assert_eq!(app.get_personal_data(EMAIL).await.status().as_u16(), 404);
+    assert_eq!(app.delete_personal_data(EMAIL).await.status().as_u16(), 404);
+    assert_eq!(
+        app.get_personal_data("not-an-email")
+            .await
+            .status()
+            .as_u16(),
+        400
+    );
+}
+
+#[actix_web::test]
+async fn test_erasure_removes_the_subscriber_and_leaves_a_tombstone() {
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.delete_personal_data(EMAIL).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    for table in [
+        "subscriptions",
+        "subscription_tokens",
+        "list_memberships",
+        "consent_events",
+        "tracking_tokens",
+        "tracking_events",
+    ] {
+        assert_eq!(
+            count_rows(&app, table).await,
+            0,
+            "{} was not emptied",
+            table
+        );
+    }
+    let tombstone = sqlx::query!("SELECT tombstone_hash FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .tombstone_hash;
+    assert!(!tombstone.contains("ursula"));
+    assert_eq!(app.get_personal_data(EMAIL).await.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_tombstones_are_keyed_with_their_own_secret() {
+    let tombstone_secret = Secret::new("tombstone-secret".to_string());
+    let app = spawn_app_with(|config| {
+        config.application.tombstone_secret = tombstone_secret.clone();
+    })
+    .await;
+    create_subscriber_with_history(&app).await;
+    app.test_user.login(&app).await;
+
+    app.delete_personal_data(EMAIL)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let tombstone = sqlx::query!("SELECT tombstone_hash FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .tombstone_hash;
+    assert_eq!(tombstone, tombstone_hash(&tombstone_secret, EMAIL));
+}
+
+#[actix_web::test]
+async fn test_erased_subscribers_can_subscribe_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.delete_personal_data(EMAIL)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    create_confirmed_subscriber(&app).await;
+
+    assert_eq!(count_rows(&app, "subscriptions").await, 1);
+}
+
+#[actix_web::test]
+async fn test_subscribers_can_export_and_erase_their_own_data() {
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+    let personal_data_url = format!("{}/subscriptions/personal-data", app.web_address);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&personal_data_url)
+        .query(&[("unsubscribe_token", &unsubscribe_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+
+    let response = client
+        .delete(&personal_data_url)
+        .query(&[("unsubscribe_token", &unsubscribe_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_rows(&app, "subscriptions").await, 0);
+    assert_eq!(count_rows(&app, "erased_subscribers").await, 1);
+
+    // The token died along with the subscriber
+    let response = client
+        .get(&personal_data_url)
+        .query(&[("unsubscribe_token", &unsubscribe_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_self_service_requires_a_valid_token() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/subscriptions/personal-data", app.web_address))
+        .query(&[("unsubscribe_token", "not-a-token")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,163 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::configuration::{RateLimit, RateLimitStoreKind, Settings};
+
+use crate::helpers::{spawn_app_with, TestingApp};
+
+fn limit(max_requests: u32) -> RateLimit {
+    RateLimit {
+        max_requests,
+        window_seconds: 3600,
+    }
+}
+
+async fn subscribe(app: &TestingApp, email: &str) -> reqwest::Response {
+    app.send_subscription_request(format!(
+        "name=le%20guin&email={}",
+        email.replace('@', "%40")
+    ))
+    .await
+}
+
+async fn mock_confirmation_emails(app: &TestingApp, n_emails: u64) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(n_emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+fn assert_is_throttled(response: &reqwest::Response) {
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response
+        .headers()
+        .get("Retry-After")
+        .expect("Missing Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[actix_web::test]
+async fn test_subscriptions_are_throttled_per_email() {
+    let app = spawn_app_with(|config| config.rate_limit.per_email = limit(2)).await;
+    mock_confirmation_emails(&app, 3).await;
+
+    for _ in 0..2 {
+        let response = subscribe(&app, "ursula_le_guin@gmail.com").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = subscribe(&app, "Ursula_Le_Guin@gmail.com").await;
+    assert_is_throttled(&response);
+
+    // Other addresses are not affected
+    let response = subscribe(&app, "ursula@example.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_subscriptions_are_throttled_per_ip() {
+    let app = spawn_app_with(|config| config.rate_limit.per_ip = limit(2)).await;
+    mock_confirmation_emails(&app, 2).await;
+
+    for email in ["a@example.com", "b@example.org"] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = subscribe(&app, "c@example.net").await;
+    assert_is_throttled(&response);
+}
+
+#[actix_web::test]
+async fn test_subscriptions_are_throttled_per_domain() {
+    let app = spawn_app_with(|config| config.rate_limit.per_domain = limit(2)).await;
+    mock_confirmation_emails(&app, 3).await;
+
+    for email in ["a@example.com", "b@example.com"] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = subscribe(&app, "c@example.com").await;
+    assert_is_throttled(&response);
+
+    let response = subscribe(&app, "c@example.org").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_resending_confirmations_is_throttled() {
+    let app = spawn_app_with(|config| config.rate_limit.per_email = limit(1)).await;
+
+    // Unknown addresses get no email, only the rate limit can turn the request down
+    let response = app.post_resend_confirmation("nobody@example.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_resend_confirmation("nobody@example.com").await;
+
+    assert_is_throttled(&response);
+}
+
+#[actix_web::test]
+async fn test_postgres_counters_throttle_subscriptions() {
+    let app = spawn_app_with(|config: &mut Settings| {
+        config.rate_limit.store = RateLimitStoreKind::Postgres;
+        config.rate_limit.per_email = limit(1);
+    })
+    .await;
+    mock_confirmation_emails(&app, 1).await;
+
+    let response = subscribe(&app, "ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = subscribe(&app, "ursula_le_guin@gmail.com").await;
+    assert_is_throttled(&response);
+
+    let hits = sqlx::query!(
+        "SELECT hits FROM rate_limit_counters WHERE key = 'email:ursula_le_guin@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .hits;
+    assert_eq!(hits, 2);
+}
+
+#[actix_web::test]
+async fn test_forwarded_addresses_are_only_trusted_behind_a_proxy() {
+    for behind_proxy in [false, true] {
+        let app = spawn_app_with(|config| {
+            config.rate_limit.behind_proxy = behind_proxy;
+            config.rate_limit.per_ip = limit(1);
+        })
+        .await;
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&app.email_server)
+            .await;
+
+        let mut statuses = Vec::new();
+        for (ip, email) in [
+            ("203.0.113.1", "a@example.com"),
+            ("203.0.113.2", "b@example.com"),
+        ] {
+            let response = reqwest::Client::new()
+                .post(format!("{}/subscriptions", app.web_address))
+                .header("X-Forwarded-For", ip)
+                .form(&[("name", "le guin"), ("email", email)])
+                .send()
+                .await
+                .unwrap();
+            statuses.push(response.status().as_u16());
+        }
+
+        if behind_proxy {
+            assert_eq!(statuses, [200, 200]);
+        } else {
+            assert_eq!(statuses, [200, 429]);
+        }
+    }
+}
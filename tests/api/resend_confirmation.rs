@@ -0,0 +1,92 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app};
+
+const SUBSCRIBER_EMAIL: &str = "ursula_le_guin@gmail.com";
+
+#[actix_web::test]
+async fn test_resend_confirmation_sends_a_working_link() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    // Move past the cooldown of the first confirmation email
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation(SUBSCRIBER_EMAIL).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_link(&email_request);
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[actix_web::test]
+async fn test_resend_confirmation_cooldown_looks_like_a_success() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation(SUBSCRIBER_EMAIL).await;
+    // Nothing is sent, but the answer must not tell that the address is pending
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("Retry-After").is_none());
+}
+
+#[actix_web::test]
+async fn test_resend_confirmation_does_nothing_for_confirmed_or_unknown_emails() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in [SUBSCRIBER_EMAIL, "someone_else@gmail.com"] {
+        let response = app.post_resend_confirmation(email).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[actix_web::test]
+async fn test_resend_confirmation_rejects_invalid_emails() {
+    let app = spawn_app().await;
+
+    let response = app.post_resend_confirmation("not-an-email").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,304 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestingApp};
+
+struct CreatedUser {
+    user_id: String,
+    username: String,
+    password: String,
+}
+
+// Created by the test user, who is an admin
+async fn create_user(app: &TestingApp, role: &str) -> CreatedUser {
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": &username,
+            "password": &password,
+            "role": role,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    CreatedUser {
+        user_id: body["user_id"].as_str().unwrap().to_string(),
+        username,
+        password,
+    }
+}
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+async fn publish_as(app: &TestingApp, user: &CreatedUser) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.web_address))
+        .basic_auth(&user.username, Some(&user.password))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[actix_web::test]
+async fn test_new_users_are_publishers_by_default() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    let user: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(user["role"], "publisher");
+}
+
+#[actix_web::test]
+async fn test_create_user_rejects_unknown_roles() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": Uuid::new_v4().to_string(),
+            "role": "owner",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_publishers_can_publish() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let publisher = create_user(&app, "publisher").await;
+
+    let response = publish_as(&app, &publisher).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_editors_cannot_publish() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+
+    let response = publish_as(&app, &editor).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The editor role is not allowed to send newsletters"));
+}
+
+#[actix_web::test]
+async fn test_editors_can_read_scheduled_newsletters() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/newsletters/scheduled", &app.web_address))
+        .basic_auth(&editor.username, Some(&editor.password))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_editors_can_write_drafts_but_not_publish_them() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+    app.post_login(&serde_json::json!({
+        "username": &editor.username,
+        "password": &editor.password,
+    }))
+    .await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Draft title",
+            "content": {"markdown": "Some *markdown*"}
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let draft: serde_json::Value = response.json().await.unwrap();
+
+    let response = app
+        .post_publish_draft(draft["draft_id"].as_str().unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[actix_web::test]
+async fn test_api_tokens_cannot_exceed_the_role_of_their_owner() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+    app.post_login(&serde_json::json!({
+        "username": &editor.username,
+        "password": &editor.password,
+    }))
+    .await;
+
+    let response = app
+        .post_create_api_token(&serde_json::json!({ "name": "ci", "scopes": ["publish"] }))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let response = app
+        .send_newsletter_with_token(newsletter_body(), body["token"].as_str().unwrap())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[actix_web::test]
+async fn test_only_admins_can_manage_users() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let publisher = create_user(&app, "publisher").await;
+    app.post_login(&serde_json::json!({
+        "username": &publisher.username,
+        "password": &publisher.password,
+    }))
+    .await;
+
+    let response = app.get_users().await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .post_create_user(&serde_json::json!({
+            "username": "new-publisher",
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .put_user_role(&app.test_user.user_id.to_string(), "editor")
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[actix_web::test]
+async fn test_admins_can_change_roles() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+
+    let response = app.put_user_role(&editor.user_id, "publisher").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = publish_as(&app, &editor).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_change_user_role_is_rejected_for_invalid_requests() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+
+    let response = app.put_user_role(&editor.user_id, "owner").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .put_user_role(&app.test_user.user_id.to_string(), "editor")
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .put_user_role(&Uuid::new_v4().to_string(), "editor")
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_editors_cannot_change_the_audience_or_read_personal_data() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let editor = create_user(&app, "editor").await;
+    app.post_login(&serde_json::json!({
+        "username": &editor.username,
+        "password": &editor.password,
+    }))
+    .await;
+    let subscriber_id = Uuid::new_v4().to_string();
+
+    let responses = vec![
+        (
+            app.post_tag_subscriber(&subscriber_id, "vip").await,
+            "tag a subscriber",
+        ),
+        (
+            app.delete_subscriber_tag(&subscriber_id, "vip").await,
+            "untag a subscriber",
+        ),
+        (
+            app.post_create_list(&serde_json::json!({ "slug": "weekly", "name": "Weekly" }))
+                .await,
+            "create a list",
+        ),
+        (
+            app.get_personal_data("ursula_le_guin@gmail.com").await,
+            "export personal data",
+        ),
+        (
+            app.delete_personal_data("ursula_le_guin@gmail.com").await,
+            "erase personal data",
+        ),
+        (
+            app.get_subscriber_consent(&subscriber_id).await,
+            "export the consent log",
+        ),
+    ];
+
+    for (response, action) in responses {
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "An editor was allowed to {}.",
+            action
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_publishers_can_manage_the_audience_but_not_personal_data() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let publisher = create_user(&app, "publisher").await;
+    app.post_login(&serde_json::json!({
+        "username": &publisher.username,
+        "password": &publisher.password,
+    }))
+    .await;
+
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": "weekly", "name": "Weekly" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.delete_personal_data("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,163 @@
+use chrono::{Duration, Utc};
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, BatchDelivered, TestingApp};
+
+fn scheduled_newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "send_at": Utc::now() + Duration::days(1),
+    })
+}
+
+async fn schedule_newsletter(app: &TestingApp) -> String {
+    let response = app.send_newsletter(scheduled_newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn test_scheduled_newsletters_are_not_sent_before_they_are_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let scheduled: serde_json::Value = app.get_scheduled_newsletters().await.json().await.unwrap();
+    assert_eq!(scheduled[0]["newsletter_issue_id"], newsletter_issue_id);
+    // Nothing shows up in the archive until the issue goes out
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}",
+        app.web_address, newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_scheduled_newsletters_are_sent_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+    app.publish_scheduled_newsletters_now().await;
+    app.dispatch_all_pending_emails().await;
+
+    let scheduled: serde_json::Value = app.get_scheduled_newsletters().await.json().await.unwrap();
+    assert_eq!(scheduled, serde_json::json!([]));
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}",
+        app.web_address, newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_send_date_in_the_past_is_rejected() {
+    let app = spawn_app().await;
+
+    let mut body = scheduled_newsletter_body();
+    body["send_at"] = serde_json::json!(Utc::now() - Duration::hours(1));
+    let response = app.send_newsletter(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_cancelled_newsletters_are_never_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+    let response = app.post_cancel_newsletter(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.publish_scheduled_newsletters_now().await;
+    app.dispatch_all_pending_emails().await;
+
+    // A cancelled issue cannot be cancelled or rescheduled again
+    let response = app.post_cancel_newsletter(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[actix_web::test]
+async fn test_rescheduling_updates_the_send_date() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = schedule_newsletter(&app).await;
+
+    let send_at = (Utc::now() + Duration::days(7)).to_rfc3339();
+    let response = app
+        .post_reschedule_newsletter(
+            &newsletter_issue_id,
+            &serde_json::json!({ "send_at": send_at }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let scheduled: serde_json::Value = app.get_scheduled_newsletters().await.json().await.unwrap();
+    let stored_send_at: chrono::DateTime<Utc> =
+        serde_json::from_value(scheduled[0]["send_at"].clone()).unwrap();
+    assert_eq!(
+        stored_send_at.timestamp(),
+        chrono::DateTime::parse_from_rfc3339(&send_at)
+            .unwrap()
+            .timestamp()
+    );
+}
+
+#[actix_web::test]
+async fn test_managing_unknown_issues_returns_404() {
+    let app = spawn_app().await;
+    let newsletter_issue_id = uuid::Uuid::new_v4().to_string();
+
+    let response = app.post_cancel_newsletter(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let send_at = Utc::now() + Duration::days(1);
+    let response = app
+        .post_reschedule_newsletter(
+            &newsletter_issue_id,
+            &serde_json::json!({ "send_at": send_at }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_scheduled_newsletters_require_authentication() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/newsletters/scheduled", app.web_address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,118 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_confirmed_subscriber_with_email,
+    spawn_app, BatchDelivered,
+};
+
+fn newsletter_body(segment: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "segment": segment,
+    })
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_manage_tags() {
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4().to_string();
+
+    assert_is_redirect_to(&app.get_subscribers().await, "/login");
+    assert_is_redirect_to(
+        &app.post_tag_subscriber(&subscriber_id, "beta").await,
+        "/login",
+    );
+    assert_is_redirect_to(
+        &app.delete_subscriber_tag(&subscriber_id, "beta").await,
+        "/login",
+    );
+}
+
+#[actix_web::test]
+async fn test_subscribers_can_be_tagged_and_untagged() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscribers: serde_json::Value = app.get_subscribers().await.json().await.unwrap();
+    let subscriber_id = subscribers[0]["subscriber_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Tagging twice is harmless
+    for _ in 0..2 {
+        let response = app.post_tag_subscriber(&subscriber_id, "beta").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.post_tag_subscriber(&subscriber_id, "eu").await;
+    let subscribers: serde_json::Value = app.get_subscribers().await.json().await.unwrap();
+    assert_eq!(subscribers[0]["tags"], serde_json::json!(["beta", "eu"]));
+
+    let response = app.delete_subscriber_tag(&subscriber_id, "beta").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: serde_json::Value = app.get_subscribers().await.json().await.unwrap();
+    assert_eq!(subscribers[0]["tags"], serde_json::json!(["eu"]));
+}
+
+#[actix_web::test]
+async fn test_tagging_unknown_subscribers_or_invalid_tags_fails() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_tag_subscriber(&Uuid::new_v4().to_string(), "beta")
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let subscribers: serde_json::Value = app.get_subscribers().await.json().await.unwrap();
+    let subscriber_id = subscribers[0]["subscriber_id"].as_str().unwrap();
+    let response = app.post_tag_subscriber(subscriber_id, "Not Valid").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_segmented_issues_only_reach_matching_subscribers() {
+    let app = spawn_app().await;
+    let beta_eu = create_confirmed_subscriber_with_email(&app, "beta_eu@example.com").await;
+    let beta = create_confirmed_subscriber_with_email(&app, "beta@example.com").await;
+    create_confirmed_subscriber_with_email(&app, "untagged@example.com").await;
+    app.test_user.login(&app).await;
+    app.post_tag_subscriber(&beta_eu, "beta").await;
+    app.post_tag_subscriber(&beta_eu, "eu").await;
+    app.post_tag_subscriber(&beta, "beta").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(newsletter_body("beta AND NOT eu"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let newsletters = app.delivered_emails().await;
+    assert_eq!(newsletters.len(), 1);
+    assert_eq!(newsletters[0]["To"], "beta@example.com");
+}
+
+#[actix_web::test]
+async fn test_invalid_segment_expressions_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.send_newsletter(newsletter_body("beta AND")).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
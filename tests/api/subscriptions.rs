@@ -34,10 +34,16 @@ async fn test_subscribe_persists_subscriber() {
 
     // Assert that the actual db operation has been done
     // sqlx::query!() defines a db_data struct at compile time with one field per column
-    let db_data = sqlx::query!("SELECT email, name, status FROM subscriptions",)
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch subscriptions");
+    let db_data = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, m.status
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch subscriptions");
 
     assert_eq!(db_data.email, "ursula_le_guin@gmail.com");
     assert_eq!(db_data.name, "le guin");
@@ -140,7 +146,8 @@ async fn test_subscribe_twice_confirmed() {
     let response = reqwest::get(confirmation_link.text_link).await.unwrap();
     assert_eq!(response.status().as_u16(), 200);
 
-    // Resend subscription request once already subscribed
+    // Resend subscription request once already subscribed, there is nothing left to confirm
     let response = app.send_subscription_request(body.into()).await;
-    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
 }
@@ -0,0 +1,158 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, BatchDelivered, TestingApp};
+
+// Publishes an issue linking to `https://example.com/docs` and returns its id along with the html
+// the subscriber received
+async fn publish_tracked_newsletter(app: &TestingApp) -> (String, String) {
+    create_confirmed_subscriber(app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .send_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": r#"<p>Read the <a href="https://example.com/docs">docs</a></p>"#,
+            },
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let body = app.delivered_emails().await.pop().unwrap();
+    (
+        newsletter_issue_id.to_string(),
+        body["HtmlBody"].as_str().unwrap().to_string(),
+    )
+}
+
+// Address of the first tracking endpoint of the given kind found in `html`, on the test server
+fn tracking_link(app: &TestingApp, html: &str, kind: &str) -> String {
+    let prefix = format!("/t/{}/", kind);
+    let start = html.find(&prefix).expect("No tracking link found");
+    let length = html[start..].find('"').unwrap();
+    format!("{}{}", app.web_address, &html[start..start + length])
+}
+
+#[actix_web::test]
+async fn test_sent_issues_are_tracked() {
+    let app = spawn_app().await;
+
+    let (_, html) = publish_tracked_newsletter(&app).await;
+
+    assert!(!html.contains("href=\"https://example.com/docs\""));
+    assert!(html.contains("/t/c/"));
+    assert!(html.contains("<img src=\"http://127.0.0.1/t/o/"));
+}
+
+#[actix_web::test]
+async fn test_clicks_redirect_to_the_original_link() {
+    let app = spawn_app().await;
+    let (_, html) = publish_tracked_newsletter(&app).await;
+
+    let response = app
+        .api_client
+        .get(tracking_link(&app, &html, "c"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/docs"
+    );
+}
+
+#[actix_web::test]
+async fn test_opens_return_a_pixel() {
+    let app = spawn_app().await;
+    let (_, html) = publish_tracked_newsletter(&app).await;
+
+    let response = reqwest::get(tracking_link(&app, &html, "o")).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "image/gif");
+}
+
+#[actix_web::test]
+async fn test_unknown_tracking_tokens() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/t/c/unknown", app.web_address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Opens are not worth breaking the email over
+    let response = reqwest::get(format!("{}/t/o/unknown", app.web_address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_stats_aggregate_opens_and_clicks() {
+    let app = spawn_app().await;
+    let (newsletter_issue_id, html) = publish_tracked_newsletter(&app).await;
+
+    for _ in 0..2 {
+        reqwest::get(tracking_link(&app, &html, "o")).await.unwrap();
+        app.api_client
+            .get(tracking_link(&app, &html, "c"))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let response = app.get_newsletter_stats(&newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["opens"], 2);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["clicks"], 2);
+    assert_eq!(stats["unique_clicks"], 1);
+    assert_eq!(
+        stats["top_links"],
+        serde_json::json!([{"url": "https://example.com/docs", "clicks": 2, "unique_clicks": 1}])
+    );
+}
+
+#[actix_web::test]
+async fn test_stats_require_authentication() {
+    let app = spawn_app().await;
+    let (newsletter_issue_id, _) = publish_tracked_newsletter(&app).await;
+
+    let response = reqwest::get(format!(
+        "{}/newsletters/{}/stats",
+        app.web_address, newsletter_issue_id
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_stats_of_unknown_issues_are_not_found() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_stats(&Uuid::new_v4().to_string()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
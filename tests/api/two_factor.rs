@@ -0,0 +1,222 @@
+use chrono::{Duration, Utc};
+use zero2prod::totp;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestingApp};
+
+// Enrols the test user, returning the secret and the recovery codes. The enrolment spends the
+// code of the current time step, later logins use the code of the next one
+async fn enrol_test_user(app: &TestingApp) -> (String, Vec<String>) {
+    app.test_user.login(app).await;
+    let enrolment: serde_json::Value = app.post_start_two_factor().await.json().await.unwrap();
+    let secret = enrolment["secret"].as_str().unwrap().to_string();
+
+    let response = app
+        .post_confirm_two_factor(&totp::code_at(&secret, Utc::now()).unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let recovery_codes = body["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    app.post_logout().await;
+    (secret, recovery_codes)
+}
+
+fn next_code(secret: &str) -> String {
+    totp::code_at(secret, Utc::now() + Duration::seconds(30)).unwrap()
+}
+
+async fn login_with_code(app: &TestingApp, code: Option<&str>) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+        "two_factor_code": code.unwrap_or(""),
+    }))
+    .await
+}
+
+async fn publish_with_code(app: &TestingApp, code: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.web_address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }));
+    if let Some(code) = code {
+        request = request.header("X-Two-Factor-Code", code);
+    }
+    request.send().await.expect("Failed to execute request")
+}
+
+#[actix_web::test]
+async fn test_you_must_be_logged_in_to_enrol() {
+    let app = spawn_app().await;
+
+    let response = app.post_start_two_factor().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn test_enrolment_returns_a_provisioning_uri() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_start_two_factor().await;
+    assert_eq!(response.status().as_u16(), 201);
+    let enrolment: serde_json::Value = response.json().await.unwrap();
+
+    let secret = enrolment["secret"].as_str().unwrap();
+    let uri = enrolment["provisioning_uri"].as_str().unwrap();
+    assert!(uri.starts_with("otpauth://totp/zero2prod:"));
+    assert!(uri.contains(&app.test_user.username));
+    assert!(uri.contains(&format!("secret={}", secret)));
+
+    // Nothing is enforced until a first code is confirmed
+    let status: serde_json::Value = app.get_two_factor_status().await.json().await.unwrap();
+    assert_eq!(status["enrolled"], false);
+}
+
+#[actix_web::test]
+async fn test_enrolment_is_only_confirmed_with_a_valid_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_start_two_factor().await;
+
+    let response = app.post_confirm_two_factor("000000x").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let status: serde_json::Value = app.get_two_factor_status().await.json().await.unwrap();
+    assert_eq!(status["enrolled"], false);
+}
+
+#[actix_web::test]
+async fn test_confirmed_enrolments_come_with_recovery_codes() {
+    let app = spawn_app().await;
+    let (secret, recovery_codes) = enrol_test_user(&app).await;
+    login_with_code(&app, Some(&next_code(&secret))).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    let stored = sqlx::query!("SELECT code_hash FROM two_factor_recovery_codes")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 10);
+    assert!(stored
+        .iter()
+        .all(|row| !recovery_codes.contains(&row.code_hash)));
+
+    let response = app.post_start_two_factor().await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[actix_web::test]
+async fn test_enrolled_users_need_a_code_to_log_in() {
+    let app = spawn_app().await;
+    let (secret, _) = enrol_test_user(&app).await;
+
+    let response = login_with_code(&app, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Please enter the code from your authenticator app."));
+
+    let response = login_with_code(&app, Some(&next_code(&secret))).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn test_codes_cannot_be_replayed() {
+    let app = spawn_app().await;
+    let (secret, _) = enrol_test_user(&app).await;
+    let code = next_code(&secret);
+
+    let response = login_with_code(&app, Some(&code)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = login_with_code(&app, Some(&code)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_recovery_codes_work_once() {
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enrol_test_user(&app).await;
+
+    let response = login_with_code(&app, Some(&recovery_codes[0])).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = login_with_code(&app, Some(&recovery_codes[0])).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_publishing_with_credentials_needs_a_code() {
+    let app = spawn_app().await;
+    let (secret, _) = enrol_test_user(&app).await;
+
+    let response = publish_with_code(&app, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = publish_with_code(&app, Some(&next_code(&secret))).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn test_wrong_codes_count_as_failed_logins() {
+    let app = spawn_app().await;
+    let (secret, _) = enrol_test_user(&app).await;
+
+    for _ in 0..3 {
+        let response = login_with_code(&app, Some("000000x")).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = login_with_code(&app, Some(&next_code(&secret))).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[actix_web::test]
+async fn test_two_factor_can_be_disabled_with_a_code() {
+    let app = spawn_app().await;
+    let (secret, recovery_codes) = enrol_test_user(&app).await;
+    login_with_code(&app, Some(&recovery_codes[0])).await;
+
+    let response = app.delete_two_factor("000000x").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.delete_two_factor(&next_code(&secret)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login_with_code(&app, None).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn test_wrong_codes_to_disable_two_factor_count_as_failed_logins() {
+    let app = spawn_app().await;
+    let (secret, recovery_codes) = enrol_test_user(&app).await;
+    login_with_code(&app, Some(&recovery_codes[0])).await;
+
+    for _ in 0..3 {
+        let response = app.delete_two_factor("000000x").await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    // Locked out, even with the right code
+    let response = app.delete_two_factor(&next_code(&secret)).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().get("Retry-After").is_some());
+    let status: serde_json::Value = app.get_two_factor_status().await.json().await.unwrap();
+    assert_eq!(status["enrolled"], true);
+}
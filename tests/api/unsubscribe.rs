@@ -0,0 +1,209 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, BatchDelivered,
+    TestingApp,
+};
+
+async fn get_unsubscribe_token(app: &TestingApp) -> String {
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the unsubscribe token")
+        .unsubscribe_token
+}
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[actix_web::test]
+async fn test_newsletters_carry_unsubscribe_link_and_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.send_newsletter(newsletter_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let body = app.delivered_emails().await.pop().unwrap();
+
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(&unsubscribe_token));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(&unsubscribe_token));
+    let headers = body["Headers"].as_array().unwrap();
+    assert_eq!(headers[0]["Name"], "List-Unsubscribe");
+    assert!(headers[0]["Value"]
+        .as_str()
+        .unwrap()
+        .contains(&unsubscribe_token));
+    assert_eq!(headers[1]["Name"], "List-Unsubscribe-Post");
+    assert_eq!(headers[1]["Value"], "List-Unsubscribe=One-Click");
+}
+
+#[actix_web::test]
+async fn test_unsubscribe_page_does_not_unsubscribe_on_its_own() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        app.web_address, unsubscribe_token
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[actix_web::test]
+async fn test_unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+
+    // One-click unsubscribe request, as sent by mail clients
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, unsubscribe_token
+        ))
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(BatchDelivered)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.send_newsletter(newsletter_body()).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn test_unsubscribed_subscribers_can_subscribe_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let response = app.send_subscription_request(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn test_old_confirmation_links_do_not_subscribe_again() {
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(confirmation_link.html_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let unsubscribe_token = get_unsubscribe_token(&app).await;
+    reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.web_address, unsubscribe_token
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(confirmation_link.html_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+    let status = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unsubscribed");
+}
+
+#[actix_web::test]
+async fn test_unsubscribe_with_invalid_or_unknown_token_fails() {
+    let app = spawn_app().await;
+
+    let cases = vec![
+        ("not-a-valid-token", 400),
+        ("cdef146lnj09inI890nhBKLk0", 401),
+    ];
+    for (token, expected_status) in cases {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+                app.web_address, token
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+}